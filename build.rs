@@ -0,0 +1,141 @@
+//! Generates the `SqlState` enum from PostgreSQL's `src/errcodes.txt`.
+use std::{env, fmt::Write as _, fs, path::Path};
+
+struct Code {
+    code: String,
+    macro_name: String,
+    name: String,
+    variant: String,
+}
+
+struct Class {
+    class: String,
+    description: String,
+    method: String,
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=src/errcodes.txt");
+    let errcodes = fs::read_to_string("src/errcodes.txt").expect("reading src/errcodes.txt");
+    let (codes, classes) = parse(&errcodes);
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("sqlstate.rs");
+    fs::write(out, generate(&codes, &classes)).expect("writing sqlstate.rs");
+}
+
+fn parse(errcodes: &str) -> (Vec<Code>, Vec<Class>) {
+    let mut codes = vec![];
+    let mut classes = vec![];
+    for line in errcodes.lines() {
+        if let Some(section) = line.strip_prefix("Section: Class ") {
+            let (class, description) = section.split_once(" - ").expect("section description");
+            // Drop remarks like "(SQL/MED)" from the method name.
+            let title = description.split(" (").next().unwrap();
+            let method = title
+                .split(|c: char| !c.is_ascii_alphanumeric())
+                .filter(|word| !word.is_empty())
+                .map(str::to_ascii_lowercase)
+                .collect::<Vec<_>>()
+                .join("_");
+            classes.push(Class {
+                class: class.to_string(),
+                description: description.to_string(),
+                method: format!("is_{}", method),
+            });
+            continue;
+        }
+        let fields: Vec<_> = line.split_whitespace().collect();
+        // Lines without a condition name are aliases for another code.
+        if line.starts_with('#') || fields.len() != 4 {
+            continue;
+        }
+        codes.push(Code {
+            code: fields[0].to_string(),
+            macro_name: fields[2].trim_start_matches("ERRCODE_").to_string(),
+            name: fields[3].to_string(),
+            variant: String::new(),
+        });
+    }
+    // Variants are named after their conditions, except where two codes
+    // share a condition name, which use their C macro names instead.
+    for i in 0..codes.len() {
+        let shared = codes.iter().filter(|c| c.name == codes[i].name).count() > 1;
+        let name = if shared {
+            &codes[i].macro_name
+        } else {
+            &codes[i].name
+        };
+        codes[i].variant = camel_case(name);
+    }
+    (codes, classes)
+}
+
+fn camel_case(name: &str) -> String {
+    name.split('_')
+        .map(|word| word[..1].to_ascii_uppercase() + &word[1..].to_ascii_lowercase())
+        .collect()
+}
+
+fn generate(codes: &[Code], classes: &[Class]) -> String {
+    let mut out = String::new();
+    out.push_str(
+        "/// A SQLSTATE code, with a variant for each condition in PostgreSQL's\n\
+         /// errcodes.txt.  Unknown codes are kept as `Other`.\n\
+         #[derive(Clone, Debug, Eq, Hash, PartialEq)]\n\
+         pub enum SqlState {\n",
+    );
+    for code in codes {
+        writeln!(out, "    /// `{}`: {}", code.code, code.name).unwrap();
+        writeln!(out, "    {},", code.variant).unwrap();
+    }
+    out.push_str("    /// A code that is not in errcodes.txt.\n    Other(String),\n}\n\n");
+
+    out.push_str("impl SqlState {\n");
+    out.push_str("    pub fn from_code(code: &str) -> SqlState {\n        match code {\n");
+    for code in codes {
+        writeln!(
+            out,
+            "            {:?} => SqlState::{},",
+            code.code, code.variant
+        )
+        .unwrap();
+    }
+    out.push_str("            _ => SqlState::Other(code.to_string()),\n        }\n    }\n\n");
+
+    out.push_str("    /// The five-character code.\n");
+    out.push_str("    pub fn code(&self) -> &str {\n        match *self {\n");
+    for code in codes {
+        writeln!(
+            out,
+            "            SqlState::{} => {:?},",
+            code.variant, code.code
+        )
+        .unwrap();
+    }
+    out.push_str("            SqlState::Other(ref code) => code,\n        }\n    }\n\n");
+
+    out.push_str("    /// The condition name, as used by PL/pgSQL, or `None` for unknown codes.\n");
+    out.push_str("    pub fn name(&self) -> Option<&'static str> {\n        Some(match *self {\n");
+    for code in codes {
+        writeln!(
+            out,
+            "            SqlState::{} => {:?},",
+            code.variant, code.name
+        )
+        .unwrap();
+    }
+    out.push_str("            SqlState::Other(_) => return None,\n        })\n    }\n");
+
+    for class in classes {
+        writeln!(
+            out,
+            "\n    /// Whether the code is in class {}: {}.",
+            class.class, class.description
+        )
+        .unwrap();
+        writeln!(out, "    pub fn {}(&self) -> bool {{", class.method).unwrap();
+        writeln!(out, "        self.class() == {:?}", class.class).unwrap();
+        out.push_str("    }\n");
+    }
+    out.push_str("}\n");
+    out
+}
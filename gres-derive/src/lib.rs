@@ -0,0 +1,143 @@
+//! `#[derive(FromRow)]` for gres.  Use it through the `derive` feature of
+//! the `gres` crate, which re-exports it next to the `FromRow` trait.
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Field, Fields, LitStr, Result,
+    Type,
+};
+
+#[proc_macro_derive(FromRow, attributes(gres))]
+pub fn derive_from_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    input.ident.span(),
+                    "FromRow can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                input.ident.span(),
+                "FromRow can only be derived for structs",
+            ))
+        }
+    };
+    let field_values = fields.iter().map(field_value).collect::<Result<Vec<_>>>()?;
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::gres::row::FromRow for #name #ty_generics #where_clause {
+            fn from_row(row: &::gres::Row) -> ::gres::Result<Self> {
+                ::std::result::Result::Ok(#name {
+                    #(#field_values,)*
+                })
+            }
+        }
+    })
+}
+
+/// The settings from a field's `#[gres(...)]` attributes.
+#[derive(Default)]
+struct FieldAttrs {
+    rename: Option<LitStr>,
+    default: bool,
+    flatten: bool,
+    try_from: Option<Type>,
+}
+
+impl FieldAttrs {
+    fn parse(field: &Field) -> Result<FieldAttrs> {
+        let mut attrs = FieldAttrs::default();
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("gres"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    attrs.rename = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("default") {
+                    attrs.default = true;
+                } else if meta.path.is_ident("flatten") {
+                    attrs.flatten = true;
+                } else if meta.path.is_ident("try_from") {
+                    // Accept both `try_from = "i64"` and `try_from = i64`.
+                    let value = meta.value()?;
+                    attrs.try_from = Some(if value.peek(LitStr) {
+                        value.parse::<LitStr>()?.parse()?
+                    } else {
+                        value.parse()?
+                    });
+                } else {
+                    return Err(meta.error("unknown gres attribute"));
+                }
+                Ok(())
+            })?;
+        }
+        if attrs.flatten && (attrs.rename.is_some() || attrs.default || attrs.try_from.is_some()) {
+            return Err(Error::new(
+                field.span(),
+                "`flatten` cannot be combined with `rename`, `default` or `try_from`",
+            ));
+        }
+        Ok(attrs)
+    }
+}
+
+/// The `field: value` initializer for one field.
+fn field_value(field: &Field) -> Result<TokenStream2> {
+    let attrs = FieldAttrs::parse(field)?;
+    let ident = field.ident.as_ref().expect("named fields have names");
+    let ty = &field.ty;
+    if attrs.flatten {
+        return Ok(quote! {
+            #ident: <#ty as ::gres::row::FromRow>::from_row(row)?
+        });
+    }
+
+    let column = match &attrs.rename {
+        Some(rename) => rename.value(),
+        None => ident.to_string().trim_start_matches("r#").to_string(),
+    };
+    let get = match &attrs.try_from {
+        Some(source) => quote! {
+            row.try_get::<#source>(#column).and_then(|value| {
+                <#ty as ::std::convert::TryFrom<#source>>::try_from(value).map_err(|err| {
+                    ::gres::error::PgError::Column(
+                        #column.to_string(),
+                        ::gres::error::ColumnError::Decode(::std::boxed::Box::new(
+                            ::gres::error::PgError::Error(err.to_string()),
+                        )),
+                    )
+                })
+            })
+        },
+        None => quote! { row.try_get::<#ty>(#column) },
+    };
+    if attrs.default {
+        Ok(quote! {
+            #ident: match #get {
+                ::std::result::Result::Err(::gres::error::PgError::Column(
+                    _,
+                    ::gres::error::ColumnError::NotFound,
+                )) => ::std::default::Default::default(),
+                result => result?,
+            }
+        })
+    } else {
+        Ok(quote! { #ident: #get? })
+    }
+}
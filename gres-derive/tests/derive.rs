@@ -0,0 +1,87 @@
+use gres::{
+    error::{ColumnError, PgError},
+    Config, Connection, FromRow, FromSql,
+};
+
+#[derive(Debug, FromRow, PartialEq)]
+struct Address {
+    city: String,
+    #[gres(rename = "zip_code")]
+    zip: String,
+}
+
+#[derive(Debug, FromRow, PartialEq)]
+struct Person {
+    id: i32,
+    #[gres(rename = "full_name")]
+    name: String,
+    nickname: Option<String>,
+    #[gres(default)]
+    score: i64,
+    #[gres(try_from = "i32")]
+    age: u8,
+    #[gres(flatten)]
+    address: Address,
+}
+
+/// Generic structs need their own bounds; the derive adds none.
+#[derive(Debug, FromRow, PartialEq)]
+struct Wrapper<T: for<'a> FromSql<'a>> {
+    r#type: T,
+}
+
+fn connect() -> Connection {
+    Config::new()
+        .connect()
+        .expect("Could not establish connection")
+}
+
+fn person_query(age: i32) -> String {
+    format!(
+        "SELECT 1::int4 AS id, 'Ann'::text AS full_name, NULL::text AS nickname, \
+         {}::int4 AS age, 'Oslo'::text AS city, '0150'::text AS zip_code;",
+        age
+    )
+}
+
+#[test]
+fn test_derive_from_row() {
+    let conn = connect();
+    let rows = conn.query(&person_query(42), &[]).unwrap();
+    assert_eq!(
+        Person::from_row(&rows[0]).unwrap(),
+        Person {
+            id: 1,
+            name: "Ann".to_string(),
+            nickname: None,
+            score: 0,
+            age: 42,
+            address: Address {
+                city: "Oslo".to_string(),
+                zip: "0150".to_string(),
+            },
+        }
+    );
+
+    let rows = conn.query("SELECT true AS type;", &[]).unwrap();
+    assert_eq!(
+        Wrapper::<bool>::from_row(&rows[0]).unwrap(),
+        Wrapper { r#type: true }
+    );
+}
+
+#[test]
+fn test_derive_from_row_errors() {
+    let conn = connect();
+    let rows = conn.query(&person_query(300), &[]).unwrap();
+    match Person::from_row(&rows[0]) {
+        Err(PgError::Column(column, ColumnError::Decode(_))) => assert_eq!(column, "age"),
+        other => panic!("Expected a conversion error, got {:?}", other),
+    }
+
+    let rows = conn.query("SELECT 'Oslo'::text AS city;", &[]).unwrap();
+    match Address::from_row(&rows[0]) {
+        Err(PgError::Column(column, ColumnError::NotFound)) => assert_eq!(column, "zip_code"),
+        other => panic!("Expected a missing column, got {:?}", other),
+    }
+}
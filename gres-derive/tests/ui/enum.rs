@@ -0,0 +1,9 @@
+use gres::FromRow;
+
+#[derive(FromRow)]
+enum Shape {
+    Circle,
+    Square,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use gres::FromRow;
+
+struct Email(String);
+
+#[derive(FromRow)]
+struct User {
+    email: Email,
+}
+
+fn main() {}
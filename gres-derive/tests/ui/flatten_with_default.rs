@@ -0,0 +1,14 @@
+use gres::FromRow;
+
+#[derive(FromRow)]
+struct Inner {
+    id: i32,
+}
+
+#[derive(FromRow)]
+struct Outer {
+    #[gres(flatten, default)]
+    inner: Inner,
+}
+
+fn main() {}
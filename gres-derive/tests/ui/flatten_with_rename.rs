@@ -0,0 +1,14 @@
+use gres::FromRow;
+
+#[derive(FromRow)]
+struct Inner {
+    id: i32,
+}
+
+#[derive(FromRow)]
+struct Outer {
+    #[gres(flatten, rename = "inner")]
+    inner: Inner,
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use gres::FromRow;
+
+#[derive(FromRow)]
+struct Point(i32, i32);
+
+fn main() {}
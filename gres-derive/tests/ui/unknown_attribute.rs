@@ -0,0 +1,9 @@
+use gres::FromRow;
+
+#[derive(FromRow)]
+struct User {
+    #[gres(skip)]
+    id: i32,
+}
+
+fn main() {}
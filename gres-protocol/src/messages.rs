@@ -1,2 +1,3 @@
 pub mod client;
+pub mod decoder;
 pub mod server;
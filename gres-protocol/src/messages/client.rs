@@ -71,7 +71,7 @@ impl<'a> Message for StartupMessage<'a> {
             write_string(writer, "database")?;
             write_string(writer, db)?;
         }
-        for &(ref param, ref value) in &self.params {
+        for (param, value) in &self.params {
             write_string(writer, param)?;
             write_string(writer, value)?;
         }
@@ -143,7 +143,7 @@ impl<'a> Message for ParseMessage<'a> {
     }
 
     fn length(&self) -> usize {
-        self.name.len() + self.sql.len() + 4 * self.param_types.len() + 4
+        self.name.len() + 1 + self.sql.len() + 1 + 2 + 4 * self.param_types.len()
     }
 
     fn write_body<W: Write>(&self, writer: &mut W) -> ::std::io::Result<()> {
@@ -169,7 +169,8 @@ pub struct BindMessage<'a> {
     pub portal: &'a str,
     pub prepared_statement: &'a str,
     pub param_format_codes: &'a [Format],
-    pub param_values: &'a [Vec<u8>],
+    /// Parameter values, with `None` for NULL.
+    pub param_values: &'a [Option<Vec<u8>>],
     pub result_format_codes: &'a [Format],
 }
 
@@ -188,7 +189,7 @@ impl<'a> Message for BindMessage<'a> {
             + self
                 .param_values
                 .iter()
-                .map(|val| 4 + val.len())
+                .map(|val| 4 + val.as_ref().map(Vec::len).unwrap_or(0))
                 .sum::<usize>()
             + 2
             + 2 * self.result_format_codes.len()
@@ -205,8 +206,7 @@ impl<'a> Message for BindMessage<'a> {
 
         writer.write_all(&(self.param_values.len() as u16).to_be_bytes())?;
         for param in self.param_values {
-            writer.write_all(&(param.len() as u32).to_be_bytes())?;
-            writer.write_all(param)?;
+            write_nullable(writer, param.as_deref())?;
         }
         writer.write_all(&(self.result_format_codes.len() as u16).to_be_bytes())?;
         for code in self.result_format_codes {
@@ -217,9 +217,11 @@ impl<'a> Message for BindMessage<'a> {
     }
 }
 
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ExecuteMessage<'a> {
-    portal: &'a str,
-    max_rows: u32,
+    pub portal: &'a str,
+    /// Maximum number of rows to return.  Zero means no limit.
+    pub max_rows: u32,
 }
 
 impl<'a> Message for ExecuteMessage<'a> {
@@ -237,6 +239,7 @@ impl<'a> Message for ExecuteMessage<'a> {
     }
 }
 
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct SyncMessage;
 
 impl Message for SyncMessage {
@@ -280,6 +283,245 @@ impl<'a> Message for CloseMessage<'a> {
         write_string(writer, self.name)
     }
 }
+
+pub type DescribeType = CloseType;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DescribeMessage<'a> {
+    pub describe_type: DescribeType,
+    pub name: &'a str,
+}
+
+impl<'a> Message for DescribeMessage<'a> {
+    fn id(&self) -> Option<u8> {
+        Some(b'D')
+    }
+
+    fn length(&self) -> usize {
+        2 + self.name.len()
+    }
+
+    fn write_body<W: Write>(&self, writer: &mut W) -> ::std::io::Result<()> {
+        writer.write_all(&[self.describe_type as u8])?;
+        write_string(writer, self.name)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FlushMessage;
+
+impl Message for FlushMessage {
+    fn id(&self) -> Option<u8> {
+        Some(b'H')
+    }
+
+    fn length(&self) -> usize {
+        0
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CopyDataMessage<'a> {
+    pub data: &'a [u8],
+}
+
+impl<'a> Message for CopyDataMessage<'a> {
+    fn id(&self) -> Option<u8> {
+        Some(b'd')
+    }
+
+    fn length(&self) -> usize {
+        self.data.len()
+    }
+
+    fn write_body<W: Write>(&self, writer: &mut W) -> ::std::io::Result<()> {
+        writer.write_all(self.data)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CopyDoneMessage;
+
+impl Message for CopyDoneMessage {
+    fn id(&self) -> Option<u8> {
+        Some(b'c')
+    }
+
+    fn length(&self) -> usize {
+        0
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CopyFailMessage<'a> {
+    pub message: &'a str,
+}
+
+impl<'a> Message for CopyFailMessage<'a> {
+    fn id(&self) -> Option<u8> {
+        Some(b'f')
+    }
+
+    fn length(&self) -> usize {
+        self.message.len() + 1
+    }
+
+    fn write_body<W: Write>(&self, writer: &mut W) -> ::std::io::Result<()> {
+        write_string(writer, self.message)
+    }
+}
+
+fn write_nullable<W: Write>(writer: &mut W, value: Option<&[u8]>) -> ::std::io::Result<()> {
+    match value {
+        Some(value) => {
+            writer.write_all(&(value.len() as u32).to_be_bytes())?;
+            writer.write_all(value)
+        }
+        None => writer.write_all(&(-1i32).to_be_bytes()),
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FunctionCallMessage<'a> {
+    pub function_oid: u32,
+    pub arg_format_codes: &'a [Format],
+    pub args: &'a [Option<Vec<u8>>],
+    pub result_format: Format,
+}
+
+impl<'a> Message for FunctionCallMessage<'a> {
+    fn id(&self) -> Option<u8> {
+        Some(b'F')
+    }
+
+    fn length(&self) -> usize {
+        4 + 2
+            + 2 * self.arg_format_codes.len()
+            + 2
+            + self
+                .args
+                .iter()
+                .map(|arg| 4 + arg.as_ref().map(Vec::len).unwrap_or(0))
+                .sum::<usize>()
+            + 2
+    }
+
+    fn write_body<W: Write>(&self, writer: &mut W) -> ::std::io::Result<()> {
+        writer.write_all(&self.function_oid.to_be_bytes())?;
+        writer.write_all(&(self.arg_format_codes.len() as u16).to_be_bytes())?;
+        for code in self.arg_format_codes {
+            writer.write_all(&(*code as u16).to_be_bytes())?;
+        }
+        writer.write_all(&(self.args.len() as u16).to_be_bytes())?;
+        for arg in self.args {
+            write_nullable(writer, arg.as_deref())?;
+        }
+        writer.write_all(&(self.result_format as u16).to_be_bytes())
+    }
+}
+
+const CANCEL_REQUEST_CODE: u32 = 80_877_102;
+const SSL_REQUEST_CODE: u32 = 80_877_103;
+const GSSENC_REQUEST_CODE: u32 = 80_877_104;
+
+/// Sent on a new connection, in place of a StartupMessage, to cancel the
+/// query running on another backend.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CancelRequest {
+    pub process_id: u32,
+    pub secret_key: u32,
+}
+
+impl Message for CancelRequest {
+    fn id(&self) -> Option<u8> {
+        None
+    }
+
+    fn length(&self) -> usize {
+        12
+    }
+
+    fn write_body<W: Write>(&self, writer: &mut W) -> ::std::io::Result<()> {
+        writer.write_all(&CANCEL_REQUEST_CODE.to_be_bytes())?;
+        writer.write_all(&self.process_id.to_be_bytes())?;
+        writer.write_all(&self.secret_key.to_be_bytes())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SslRequest;
+
+impl Message for SslRequest {
+    fn id(&self) -> Option<u8> {
+        None
+    }
+
+    fn length(&self) -> usize {
+        4
+    }
+
+    fn write_body<W: Write>(&self, writer: &mut W) -> ::std::io::Result<()> {
+        writer.write_all(&SSL_REQUEST_CODE.to_be_bytes())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct GssEncRequest;
+
+impl Message for GssEncRequest {
+    fn id(&self) -> Option<u8> {
+        None
+    }
+
+    fn length(&self) -> usize {
+        4
+    }
+
+    fn write_body<W: Write>(&self, writer: &mut W) -> ::std::io::Result<()> {
+        writer.write_all(&GSSENC_REQUEST_CODE.to_be_bytes())
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SaslInitialResponse<'a> {
+    pub mechanism: &'a str,
+    pub data: Option<&'a [u8]>,
+}
+
+impl<'a> Message for SaslInitialResponse<'a> {
+    fn id(&self) -> Option<u8> {
+        Some(b'p')
+    }
+
+    fn length(&self) -> usize {
+        self.mechanism.len() + 1 + 4 + self.data.map(<[u8]>::len).unwrap_or(0)
+    }
+
+    fn write_body<W: Write>(&self, writer: &mut W) -> ::std::io::Result<()> {
+        write_string(writer, self.mechanism)?;
+        write_nullable(writer, self.data)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SaslResponse<'a> {
+    pub data: &'a [u8],
+}
+
+impl<'a> Message for SaslResponse<'a> {
+    fn id(&self) -> Option<u8> {
+        Some(b'p')
+    }
+
+    fn length(&self) -> usize {
+        self.data.len()
+    }
+
+    fn write_body<W: Write>(&self, writer: &mut W) -> ::std::io::Result<()> {
+        writer.write_all(self.data)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -320,4 +562,104 @@ mod tests {
         };
         assert_eq!(msg.to_bytes(), b"Q\0\0\0\x0dSELECT 1\0".to_vec());
     }
+
+    #[test]
+    fn test_bind_message() {
+        let msg = BindMessage {
+            portal: "",
+            prepared_statement: "s1",
+            param_format_codes: &[Format::Text, Format::Binary],
+            param_values: &[Some(b"42".to_vec()), None],
+            result_format_codes: &[],
+        };
+        let bytes = msg.to_bytes();
+        assert_eq!(
+            bytes,
+            b"B\0\0\0\x1c\0s1\0\0\x02\0\0\0\x01\0\x02\0\0\0\x0242\xff\xff\xff\xff\0\0".to_vec()
+        );
+        assert_eq!(bytes.len(), msg.length() + 5);
+    }
+
+    #[test]
+    fn test_execute_message() {
+        let msg = ExecuteMessage {
+            portal: "p1",
+            max_rows: 10,
+        };
+        assert_eq!(msg.to_bytes(), b"E\0\0\0\x0bp1\0\0\0\0\x0a".to_vec());
+    }
+
+    #[test]
+    fn test_describe_message() {
+        let msg = DescribeMessage {
+            describe_type: DescribeType::PreparedStatement,
+            name: "s1",
+        };
+        assert_eq!(msg.to_bytes(), b"D\0\0\0\x08Ss1\0".to_vec());
+    }
+
+    #[test]
+    fn test_flush_and_sync() {
+        assert_eq!(FlushMessage.to_bytes(), b"H\0\0\0\x04".to_vec());
+        assert_eq!(SyncMessage.to_bytes(), b"S\0\0\0\x04".to_vec());
+    }
+
+    #[test]
+    fn test_copy_messages() {
+        let msg = CopyDataMessage { data: b"1\t2\n" };
+        assert_eq!(msg.to_bytes(), b"d\0\0\0\x081\t2\n".to_vec());
+        assert_eq!(CopyDoneMessage.to_bytes(), b"c\0\0\0\x04".to_vec());
+        let msg = CopyFailMessage { message: "nope" };
+        assert_eq!(msg.to_bytes(), b"f\0\0\0\x09nope\0".to_vec());
+    }
+
+    #[test]
+    fn test_function_call_message() {
+        let msg = FunctionCallMessage {
+            function_oid: 1598,
+            arg_format_codes: &[Format::Binary],
+            args: &[Some(vec![0, 0, 0, 1]), None],
+            result_format: Format::Text,
+        };
+        let bytes = msg.to_bytes();
+        assert_eq!(
+            bytes,
+            b"F\0\0\0\x1c\0\0\x06\x3e\0\x01\0\x01\0\x02\0\0\0\x04\0\0\0\x01\xff\xff\xff\xff\0\0"
+                .to_vec()
+        );
+        assert_eq!(bytes.len(), msg.length() + 5);
+    }
+
+    #[test]
+    fn test_startup_requests() {
+        let msg = CancelRequest {
+            process_id: 6075,
+            secret_key: 1,
+        };
+        assert_eq!(
+            msg.to_bytes(),
+            b"\0\0\0\x10\x04\xd2\x16\x2e\0\0\x17\xbb\0\0\0\x01".to_vec()
+        );
+        assert_eq!(SslRequest.to_bytes(), b"\0\0\0\x08\x04\xd2\x16\x2f".to_vec());
+        assert_eq!(GssEncRequest.to_bytes(), b"\0\0\0\x08\x04\xd2\x16\x30".to_vec());
+    }
+
+    #[test]
+    fn test_sasl_messages() {
+        let msg = SaslInitialResponse {
+            mechanism: "SCRAM-SHA-256",
+            data: Some(b"n,,n=,r=abc"),
+        };
+        assert_eq!(
+            msg.to_bytes(),
+            b"p\0\0\0\x21SCRAM-SHA-256\0\0\0\0\x0bn,,n=,r=abc".to_vec()
+        );
+        let msg = SaslInitialResponse {
+            mechanism: "X",
+            data: None,
+        };
+        assert_eq!(msg.to_bytes(), b"p\0\0\0\x0aX\0\xff\xff\xff\xff".to_vec());
+        let msg = SaslResponse { data: b"c=biws" };
+        assert_eq!(msg.to_bytes(), b"p\0\0\0\x0ac=biws".to_vec());
+    }
 }
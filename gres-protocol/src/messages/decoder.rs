@@ -0,0 +1,314 @@
+use super::server::ServerMsg;
+use crate::{ProtocolError, Result};
+use std::{
+    convert::TryInto,
+    io::{self, Read},
+};
+
+/// How much to ask for in each read, unless a larger frame is pending.
+const READ_SIZE: usize = 8 * 1024;
+
+/// The most the buffer grows by in one read.  A large frame is allocated as
+/// its data arrives, not up front from its declared length.
+const MAX_READ_SIZE: usize = 1024 * 1024;
+
+/// The largest message accepted, including the identifier.  The server
+/// builds each message in a buffer of at most 1 GiB, so a longer declared
+/// length means a corrupt or hostile stream.
+const MAX_MESSAGE_LENGTH: usize = 1 << 30;
+
+/// Incrementally decodes backend messages from a byte stream.
+///
+/// Bytes are fed in arbitrary chunks as they arrive from the socket, either
+/// with [`feed`](Self::feed) or by reading straight into the buffer with
+/// [`read_from`](Self::read_from).  Partial frames are buffered until the
+/// rest of the message arrives, and complete messages are handed out one at
+/// a time by [`decode`](Self::decode).  The buffer is reused, so it only
+/// grows to fit the largest message seen.
+#[derive(Clone, Debug, Default)]
+pub struct ServerMessageDecoder {
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl ServerMessageDecoder {
+    pub fn new() -> ServerMessageDecoder {
+        ServerMessageDecoder::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> ServerMessageDecoder {
+        ServerMessageDecoder {
+            buf: Vec::with_capacity(capacity),
+            pos: 0,
+        }
+    }
+
+    /// Append a chunk of bytes received from the server.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.compact();
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Read whatever is available from `reader` into the buffer, blocking
+    /// until at least one byte arrives.  Returns the number of bytes read,
+    /// which is 0 at end of stream.
+    pub fn read_from<R: Read>(&mut self, reader: &mut R) -> io::Result<usize> {
+        self.compact();
+        // Make room for more of a large pending frame, a bounded step at a
+        // time.
+        let wanted = match self.pending_length() {
+            Some(length) if length > self.buf.len() + READ_SIZE => {
+                (length - self.buf.len()).min(MAX_READ_SIZE)
+            }
+            _ => READ_SIZE,
+        };
+        let filled = self.buf.len();
+        self.buf.resize(filled + wanted, 0);
+        let result = loop {
+            match reader.read(&mut self.buf[filled..]) {
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result,
+            }
+        };
+        self.buf.truncate(filled + *result.as_ref().unwrap_or(&0));
+        result
+    }
+
+    /// The declared size of the frame at the front of the buffer, including
+    /// the identifier, if its header has arrived.
+    fn pending_length(&self) -> Option<usize> {
+        let pending = &self.buf[self.pos..];
+        if pending.len() < 5 {
+            return None;
+        }
+        let length = u32::from_be_bytes(pending[1..5].try_into().expect("four bytes"));
+        Some((length as usize).saturating_add(1))
+    }
+
+    /// The number of buffered bytes that have not yet been decoded.
+    pub fn buffered(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffered() == 0
+    }
+
+    /// Discard all buffered data.
+    pub fn clear(&mut self) {
+        self.buf.clear();
+        self.pos = 0;
+    }
+
+    /// The length of the complete frame at the front of the buffer, if one
+    /// has arrived.
+    fn frame_length(&self) -> Result<Option<usize>> {
+        let length = match self.pending_length() {
+            Some(length) => length,
+            None => return Ok(None),
+        };
+        if !(5..=MAX_MESSAGE_LENGTH).contains(&length) {
+            return Err(ProtocolError::Error(format!(
+                "Invalid message length {} for message {:?}",
+                length - 1,
+                self.buf[self.pos] as char
+            )));
+        }
+        if self.buffered() < length {
+            Ok(None)
+        } else {
+            Ok(Some(length))
+        }
+    }
+
+    /// Returns true if a complete message is ready to be decoded.
+    pub fn has_frame(&self) -> Result<bool> {
+        self.frame_length().map(|length| length.is_some())
+    }
+
+    /// Take the next complete frame, including its identifier and length
+    /// header, without parsing it.
+    pub fn next_frame(&mut self) -> Result<Option<&[u8]>> {
+        match self.frame_length()? {
+            Some(length) => {
+                let start = self.pos;
+                self.pos += length;
+                Ok(Some(&self.buf[start..self.pos]))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Decode the next complete message, or return `None` if more data is
+    /// needed.
+    pub fn decode(&mut self) -> Result<Option<ServerMsg<'_>>> {
+        match self.next_frame()? {
+            Some(frame) => ServerMsg::from_slice(frame).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn compact(&mut self) {
+        if self.pos == self.buf.len() {
+            self.buf.clear();
+            self.pos = 0;
+        } else if self.pos > 0 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::server::{AuthMsg, TransactionStatus};
+
+    const STARTUP: &[u8] = b"R\x00\x00\x00\x08\x00\x00\x00\x00S\x00\x00\x00\x19client_encoding\x00UTF8\x00K\x00\x00\x00\x0c\x00\x00\x17\xbb\x15b\xfb1Z\x00\x00\x00\x05I";
+
+    #[test]
+    fn test_decode_whole_buffer() {
+        let mut decoder = ServerMessageDecoder::new();
+        decoder.feed(STARTUP);
+        assert_eq!(decoder.decode().unwrap(), Some(ServerMsg::Auth(AuthMsg::Ok)));
+        assert_eq!(
+            decoder.decode().unwrap(),
+            Some(ServerMsg::ParamStatus("client_encoding", "UTF8"))
+        );
+        assert!(matches!(
+            decoder.decode().unwrap(),
+            Some(ServerMsg::BackendKeyData(..))
+        ));
+        assert_eq!(
+            decoder.decode().unwrap(),
+            Some(ServerMsg::ReadyForQuery(TransactionStatus::Idle))
+        );
+        assert_eq!(decoder.decode().unwrap(), None);
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn test_decode_byte_at_a_time() {
+        let mut decoder = ServerMessageDecoder::new();
+        let mut count = 0;
+        for byte in STARTUP {
+            decoder.feed(&[*byte]);
+            while decoder.decode().unwrap().is_some() {
+                count += 1;
+            }
+        }
+        assert_eq!(count, 4);
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn test_partial_frame_is_buffered() {
+        let mut decoder = ServerMessageDecoder::new();
+        let (head, tail) = STARTUP.split_at(12);
+        decoder.feed(head);
+        assert_eq!(decoder.decode().unwrap(), Some(ServerMsg::Auth(AuthMsg::Ok)));
+        assert!(!decoder.has_frame().unwrap());
+        assert_eq!(decoder.decode().unwrap(), None);
+        assert_eq!(decoder.buffered(), 3);
+        decoder.feed(tail);
+        assert_eq!(
+            decoder.decode().unwrap(),
+            Some(ServerMsg::ParamStatus("client_encoding", "UTF8"))
+        );
+    }
+
+    #[test]
+    fn test_next_frame() {
+        let mut decoder = ServerMessageDecoder::new();
+        decoder.feed(&STARTUP[..9]);
+        assert_eq!(
+            decoder.next_frame().unwrap(),
+            Some(&b"R\x00\x00\x00\x08\x00\x00\x00\x00"[..])
+        );
+        assert_eq!(decoder.next_frame().unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid_length() {
+        let mut decoder = ServerMessageDecoder::new();
+        decoder.feed(b"Z\x00\x00\x00\x02");
+        assert!(decoder.decode().is_err());
+    }
+
+    #[test]
+    fn test_oversized_length() {
+        let mut decoder = ServerMessageDecoder::new();
+        decoder.feed(b"R\xff\xff\xff\xff");
+        assert!(decoder.has_frame().is_err());
+        assert!(decoder.decode().is_err());
+    }
+
+    /// Hands out at most `chunk` bytes per read.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        chunk: usize,
+        reads: usize,
+    }
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads += 1;
+            let n = self.chunk.min(buf.len()).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_read_from() {
+        let mut decoder = ServerMessageDecoder::new();
+        let mut reader = Trickle {
+            data: STARTUP,
+            chunk: 7,
+            reads: 0,
+        };
+        let mut count = 0;
+        while decoder.read_from(&mut reader).unwrap() > 0 {
+            while decoder.decode().unwrap().is_some() {
+                count += 1;
+            }
+        }
+        assert_eq!(count, 4);
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn test_read_from_large_frame() {
+        let mut frame = b"D\x00\x01\x00\x0a\x00\x01\x00\x01\x00\x00".to_vec();
+        frame.resize(1 + 0x1000a, b'x');
+        let mut decoder = ServerMessageDecoder::new();
+        let mut reader = Trickle {
+            data: &frame,
+            chunk: usize::MAX,
+            reads: 0,
+        };
+        decoder.read_from(&mut reader).unwrap();
+        assert!(!decoder.has_frame().unwrap());
+        // Once the header is known, the rest arrives in a single read.
+        decoder.read_from(&mut reader).unwrap();
+        assert!(decoder.has_frame().unwrap());
+        assert_eq!(reader.reads, 2);
+        assert_eq!(decoder.next_frame().unwrap().unwrap().len(), frame.len());
+        assert_eq!(decoder.read_from(&mut reader).unwrap(), 0);
+    }
+    #[test]
+    fn test_read_from_grows_in_steps() {
+        // A header declaring a 1 GiB frame, with nothing after it.
+        let mut decoder = ServerMessageDecoder::new();
+        let mut reader = Trickle {
+            data: b"D\x3f\xff\xff\xff",
+            chunk: usize::MAX,
+            reads: 0,
+        };
+        decoder.read_from(&mut reader).unwrap();
+        assert!(!decoder.has_frame().unwrap());
+        assert_eq!(decoder.read_from(&mut reader).unwrap(), 0);
+        assert!(decoder.buf.capacity() < 2 * MAX_READ_SIZE);
+    }
+}
@@ -9,20 +9,32 @@ pub enum ServerMsg<'a> {
     ErrorResponse(NoticeBody<'a>),
     NoticeResponse(NoticeBody<'a>),
     Auth(AuthMsg<'a>),
-    ReadyForQuery,
+    ReadyForQuery(TransactionStatus),
     CommandComplete(&'a str),
     ParamStatus(&'a str, &'a str),
     BackendKeyData(u32, u32),
     RowDescription(Vec<FieldDescription<'a>>), // TBD
-    DataRow(Vec<&'a str>),                     // TBD
+    DataRow(DataRow<'a>),
     Unknown(&'a str, &'a [u8]),                // TBD
     ParseComplete,
     BindComplete,
     CloseComplete,
+    ParameterDescription(Vec<u32>),
+    NoData,
+    PortalSuspended,
+    EmptyQueryResponse,
+    CopyInResponse(CopyResponse),
+    CopyOutResponse(CopyResponse),
+    CopyBothResponse(CopyResponse),
+    CopyData(&'a [u8]),
+    CopyDone,
+    NotificationResponse(u32, &'a str, &'a str),
+    FunctionCallResponse(Option<&'a [u8]>),
+    NegotiateProtocolVersion(u32, Vec<&'a str>),
 }
 
 impl<'a> ServerMsg<'a> {
-    pub fn from_slice(message: &[u8]) -> Result<ServerMsg> {
+    pub fn from_slice(message: &[u8]) -> Result<ServerMsg<'_>> {
         let length = 1 + slice_to_u32(&message[1..5]) as usize;
         if message.len() != length {
             return Err(ProtocolError::Error(format!(
@@ -35,14 +47,8 @@ impl<'a> ServerMsg<'a> {
         let (_, extra) = message.split_at(5);
         match identifier {
             "I" => {
-                if extra.is_empty() {
-                    Ok(ServerMsg::ParseComplete)
-                } else {
-                    Err(ProtocolError::Error(format!(
-                        "Extra value after param status: {:?}",
-                        extra
-                    )))
-                }
+                // EmptyQueryResponse
+                expect_empty(extra, ServerMsg::EmptyQueryResponse)
             }
             "R" => AuthMsg::from_slice(extra).map(ServerMsg::Auth),
             "S" => {
@@ -67,19 +73,17 @@ impl<'a> ServerMsg<'a> {
                 Ok(ServerMsg::BackendKeyData(pid, key))
             }
             "T" => {
-                println!("{:?}", extra);
                 // Row Description
-                let field_count = slice_to_u16(&extra[..2]);
-                let mut extra = &extra[2..];
-                let mut fields = vec![];
+                let (field_count, mut extra) = take_u16(extra)?;
+                let mut fields = Vec::with_capacity(field_count as usize);
 
                 for _ in 0..field_count {
-                    let (name, bytes, rem) = FieldDescription::take_field(extra).unwrap();
-                    let fd = FieldDescription::new(name, bytes).unwrap();
+                    let (name, bytes, rem) = FieldDescription::take_field(extra)?;
+                    let fd = FieldDescription::new(name, bytes)?;
                     fields.push(fd);
                     extra = rem;
                 }
-                if extra == &b""[..] {
+                if extra.is_empty() {
                     Ok(ServerMsg::RowDescription(fields))
                 } else {
                     Err(ProtocolError::Error(format!(
@@ -90,16 +94,15 @@ impl<'a> ServerMsg<'a> {
             }
             "D" => {
                 // Data Row
-                let field_count = slice_to_u16(&extra[..2]);
-                let mut extra = &extra[2..];
-                let mut fields = vec![];
+                let (field_count, mut extra) = take_u16(extra)?;
+                let mut cells = Vec::with_capacity(field_count as usize);
                 for _ in 0..field_count {
-                    let (string, more) = take_sized_string(extra).unwrap();
-                    fields.push(string);
+                    let (cell, more) = take_sized_bytes(extra)?;
+                    cells.push(cell);
                     extra = more;
                 }
-                if extra == &b""[..] {
-                    Ok(ServerMsg::DataRow(fields))
+                if extra.is_empty() {
+                    Ok(ServerMsg::DataRow(DataRow::new(cells)))
                 } else {
                     Err(ProtocolError::Error(format!(
                         "Unexpected extra data in data row: {:?}",
@@ -121,7 +124,9 @@ impl<'a> ServerMsg<'a> {
             }
             "Z" => {
                 // ReadyForQuery
-                Ok(ServerMsg::ReadyForQuery)
+                let (status, extra) = take_bytes(extra, 1)?;
+                let status = TransactionStatus::from_byte(status[0])?;
+                expect_empty(extra, ServerMsg::ReadyForQuery(status))
             }
             "N" => {
                 // NoticeResponse
@@ -134,33 +139,116 @@ impl<'a> ServerMsg<'a> {
             }
             "1" => {
                 // ParseComplete
-                if extra.is_empty() {
-                    Ok(ServerMsg::ParseComplete)
-                } else {
-                    Err(ProtocolError::Error(format!("Extra data: {:?}", extra)))
-                }
+                expect_empty(extra, ServerMsg::ParseComplete)
             }
             "2" => {
                 // BindComplete
-                if extra.is_empty() {
-                    Ok(ServerMsg::BindComplete)
-                } else {
-                    Err(ProtocolError::Error(format!("Extra data: {:?}", extra)))
-                }
+                expect_empty(extra, ServerMsg::BindComplete)
             }
             "3" => {
                 // CloseComplete
-                if extra.is_empty() {
-                    Ok(ServerMsg::CloseComplete)
-                } else {
-                    Err(ProtocolError::Error(format!("Extra data: {:?}", extra)))
+                expect_empty(extra, ServerMsg::CloseComplete)
+            }
+            "t" => {
+                // ParameterDescription
+                let (count, mut extra) = take_u16(extra)?;
+                let mut param_types = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    let (oid, rem) = take_u32(extra)?;
+                    param_types.push(oid);
+                    extra = rem;
                 }
+                expect_empty(extra, ServerMsg::ParameterDescription(param_types))
+            }
+            "n" => {
+                // NoData
+                expect_empty(extra, ServerMsg::NoData)
+            }
+            "s" => {
+                // PortalSuspended
+                expect_empty(extra, ServerMsg::PortalSuspended)
+            }
+            "G" => {
+                // CopyInResponse
+                CopyResponse::from_bytes(extra).map(ServerMsg::CopyInResponse)
+            }
+            "H" => {
+                // CopyOutResponse
+                CopyResponse::from_bytes(extra).map(ServerMsg::CopyOutResponse)
+            }
+            "W" => {
+                // CopyBothResponse
+                CopyResponse::from_bytes(extra).map(ServerMsg::CopyBothResponse)
+            }
+            "d" => {
+                // CopyData
+                Ok(ServerMsg::CopyData(extra))
+            }
+            "c" => {
+                // CopyDone
+                expect_empty(extra, ServerMsg::CopyDone)
+            }
+            "A" => {
+                // NotificationResponse
+                let (pid, extra) = take_u32(extra)?;
+                let (channel, _, extra) = take_cstring_plus_fixed(extra, 0)?;
+                let (payload, _, extra) = take_cstring_plus_fixed(extra, 0)?;
+                expect_empty(
+                    extra,
+                    ServerMsg::NotificationResponse(pid, channel, payload),
+                )
+            }
+            "V" => {
+                // FunctionCallResponse
+                let (value, extra) = take_sized_bytes(extra)?;
+                expect_empty(extra, ServerMsg::FunctionCallResponse(value))
+            }
+            "v" => {
+                // NegotiateProtocolVersion
+                let (minor_version, extra) = take_u32(extra)?;
+                let (count, mut extra) = take_u32(extra)?;
+                let mut options = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    let (option, _, rem) = take_cstring_plus_fixed(extra, 0)?;
+                    options.push(option);
+                    extra = rem;
+                }
+                expect_empty(
+                    extra,
+                    ServerMsg::NegotiateProtocolVersion(minor_version, options),
+                )
             }
             _ => Ok(ServerMsg::Unknown(identifier, extra)),
         }
     }
 }
 
+/// The backend's transaction state, as reported by ReadyForQuery.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TransactionStatus {
+    /// Not in a transaction block.
+    Idle,
+    /// In a transaction block.
+    InTransaction,
+    /// In a failed transaction block.  Queries will be rejected until the
+    /// block is ended.
+    Failed,
+}
+
+impl TransactionStatus {
+    fn from_byte(status: u8) -> Result<TransactionStatus> {
+        match status {
+            b'I' => Ok(TransactionStatus::Idle),
+            b'T' => Ok(TransactionStatus::InTransaction),
+            b'E' => Ok(TransactionStatus::Failed),
+            _ => Err(ProtocolError::Error(format!(
+                "Invalid transaction status: {:?}",
+                status as char
+            ))),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum AuthMsg<'a> {
     Ok,
@@ -171,11 +259,14 @@ pub enum AuthMsg<'a> {
     Gss,
     Sspi,
     GssContinue(&'a [u8]),
+    Sasl(Vec<&'a str>),
+    SaslContinue(&'a [u8]),
+    SaslFinal(&'a [u8]),
     Unknown,
 }
 
 impl<'a> AuthMsg<'a> {
-    pub fn from_slice(extra: &'a [u8]) -> Result<AuthMsg> {
+    pub fn from_slice(extra: &'a [u8]) -> Result<AuthMsg<'a>> {
         match slice_to_u32(&extra[0..4]) {
             0 => Ok(AuthMsg::Ok),
             2 => Ok(AuthMsg::Kerberos),
@@ -187,11 +278,27 @@ impl<'a> AuthMsg<'a> {
             6 => Ok(AuthMsg::ScmCredential),
             7 => Ok(AuthMsg::Gss),
             8 => {
-                let gss_data = &extra[4..8];
+                let gss_data = &extra[4..];
                 Ok(AuthMsg::GssContinue(gss_data))
             }
             9 => Ok(AuthMsg::Sspi),
-            1 | 4 | 10..=255 => Ok(AuthMsg::Unknown),
+            10 => {
+                // A list of mechanism names, terminated by an empty name.
+                let mut mechanisms = vec![];
+                let mut extra = &extra[4..];
+                loop {
+                    let (mechanism, _, rem) = take_cstring_plus_fixed(extra, 0)?;
+                    extra = rem;
+                    if mechanism.is_empty() {
+                        break;
+                    }
+                    mechanisms.push(mechanism);
+                }
+                expect_empty(extra, AuthMsg::Sasl(mechanisms))
+            }
+            11 => Ok(AuthMsg::SaslContinue(&extra[4..])),
+            12 => Ok(AuthMsg::SaslFinal(&extra[4..])),
+            1 | 4 | 13..=255 => Ok(AuthMsg::Unknown),
             _ => Err(ProtocolError::Other),
         }
     }
@@ -216,6 +323,46 @@ pub enum FieldFormat {
     Binary,
 }
 
+impl FieldFormat {
+    fn from_code(code: u16) -> Result<FieldFormat> {
+        match code {
+            0 => Ok(FieldFormat::Text),
+            1 => Ok(FieldFormat::Binary),
+            _ => Err(ProtocolError::Error(format!("Invalid field format: {}", code))),
+        }
+    }
+}
+
+/// The body of a CopyInResponse, CopyOutResponse or CopyBothResponse.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CopyResponse {
+    pub format: FieldFormat,
+    pub column_formats: Vec<FieldFormat>,
+}
+
+impl CopyResponse {
+    fn from_bytes(bytes: &[u8]) -> Result<CopyResponse> {
+        let (&format, extra) = bytes
+            .split_first()
+            .ok_or_else(|| ProtocolError::Error("Empty copy response".to_string()))?;
+        let format = FieldFormat::from_code(format.into())?;
+        let (count, mut extra) = take_u16(extra)?;
+        let mut column_formats = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let (code, rem) = take_u16(extra)?;
+            column_formats.push(FieldFormat::from_code(code)?);
+            extra = rem;
+        }
+        expect_empty(
+            extra,
+            CopyResponse {
+                format,
+                column_formats,
+            },
+        )
+    }
+}
+
 mod erg {
     use crate::{ProtocolError, Result};
     use std::{convert::TryInto, str::from_utf8};
@@ -232,32 +379,125 @@ mod erg {
         u16::from_be_bytes(input.try_into().expect("expected two bytes"))
     }
 
-    pub fn take_cstring_plus_fixed<'a>(
-        input: &'a [u8],
-        fixed: usize,
-    ) -> Result<(&'a str, &'a [u8], &'a [u8])> {
+    pub fn take_cstring_plus_fixed(input: &[u8], fixed: usize) -> Result<(&str, &[u8], &[u8])> {
         let strlen = find_first(input, &0);
         match strlen {
             Some(strlen) => {
                 let string = from_utf8(&input[..strlen])?;
-                let fixed_data = &input[strlen + 1..strlen + 1 + fixed];
-                let extra = &input[strlen + 1 + fixed..];
+                let (fixed_data, extra) = take_bytes(&input[strlen + 1..], fixed)?;
                 Ok((string, fixed_data, extra))
             }
             None => Err(ProtocolError::Error("null byte not found".to_string())),
         }
     }
-    pub fn take_sized_string<'a>(input: &'a [u8]) -> Result<(&'a str, &'a [u8])> {
-        let size = slice_to_u32(&input[..4]) as usize;
-        let data = from_utf8(&input[4..4 + size])?;
-        let extra = &input[4 + size..];
-        Ok((data, extra))
+    fn too_short(wanted: usize, input: &[u8]) -> ProtocolError {
+        ProtocolError::Error(format!(
+            "Expected {} more bytes, found {:?}",
+            wanted, input
+        ))
+    }
+
+    pub fn take_bytes(input: &[u8], length: usize) -> Result<(&[u8], &[u8])> {
+        if input.len() < length {
+            Err(too_short(length, input))
+        } else {
+            Ok(input.split_at(length))
+        }
+    }
+
+    pub fn take_u32(input: &[u8]) -> Result<(u32, &[u8])> {
+        let (bytes, extra) = take_bytes(input, 4)?;
+        Ok((slice_to_u32(bytes), extra))
+    }
+
+    pub fn take_u16(input: &[u8]) -> Result<(u16, &[u8])> {
+        let (bytes, extra) = take_bytes(input, 2)?;
+        Ok((slice_to_u16(bytes), extra))
+    }
+
+    pub fn expect_empty<T>(extra: &[u8], value: T) -> Result<T> {
+        if extra.is_empty() {
+            Ok(value)
+        } else {
+            Err(ProtocolError::Error(format!("Extra data: {:?}", extra)))
+        }
+    }
+
+    /// Take a length-prefixed value, where a length of -1 denotes NULL.
+    pub fn take_sized_bytes(input: &[u8]) -> Result<(Option<&[u8]>, &[u8])> {
+        let (size, extra) = take_u32(input)?;
+        if size as i32 == -1 {
+            Ok((None, extra))
+        } else {
+            let (data, extra) = take_bytes(extra, size as usize)?;
+            Ok((Some(data), extra))
+        }
+    }
+}
+
+/// The column values of a single result row.  `None` is SQL NULL.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DataRow<'a> {
+    cells: Vec<Option<&'a [u8]>>,
+}
+
+impl<'a> DataRow<'a> {
+    pub fn new(cells: Vec<Option<&'a [u8]>>) -> DataRow<'a> {
+        DataRow { cells }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn cells(&self) -> &[Option<&'a [u8]>] {
+        &self.cells
+    }
+
+    pub fn into_cells(self) -> Vec<Option<&'a [u8]>> {
+        self.cells
+    }
+
+    /// The raw bytes of column `idx`.  Panics if `idx` is out of range.
+    pub fn cell(&self, idx: usize) -> Option<&'a [u8]> {
+        self.cells[idx]
+    }
+
+    /// Decode column `idx` as text.  Only meaningful for text-format
+    /// columns.
+    pub fn text(&self, idx: usize) -> Result<Option<&'a str>> {
+        match self.cells.get(idx) {
+            Some(Some(bytes)) => Ok(Some(from_utf8(bytes)?)),
+            Some(None) => Ok(None),
+            None => Err(ProtocolError::Error(format!(
+                "Column {} out of range for row with {} columns",
+                idx,
+                self.cells.len()
+            ))),
+        }
+    }
+
+    /// Decode every column as text.
+    pub fn texts(&self) -> Result<Vec<Option<&'a str>>> {
+        self.cells
+            .iter()
+            .map(|cell| cell.map(from_utf8).transpose().map_err(Into::into))
+            .collect()
     }
 }
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct FieldDescription<'a> {
     field_name: &'a str,
+    table_oid: u32,
+    column_id: i16,
+    type_oid: u32,
+    type_size: i16,
+    type_modifier: i32,
     format: FieldFormat,
 }
 
@@ -267,16 +507,52 @@ impl<'a> FieldDescription<'a> {
     }
 
     fn new(name: &'a str, fixed_data: &'a [u8]) -> Result<FieldDescription<'a>> {
-        let format = match slice_to_u16(&fixed_data[16..18]) {
-            0 => FieldFormat::Text,
-            1 => FieldFormat::Binary,
-            _ => return Err(ProtocolError::Error("Invalid field format".to_string())),
-        };
         Ok(FieldDescription {
             field_name: name,
-            format,
+            table_oid: slice_to_u32(&fixed_data[0..4]),
+            column_id: slice_to_u16(&fixed_data[4..6]) as i16,
+            type_oid: slice_to_u32(&fixed_data[6..10]),
+            type_size: slice_to_u16(&fixed_data[10..12]) as i16,
+            type_modifier: slice_to_u32(&fixed_data[12..16]) as i32,
+            format: FieldFormat::from_code(slice_to_u16(&fixed_data[16..18]))?,
         })
     }
+
+    pub fn name(&self) -> &'a str {
+        self.field_name
+    }
+
+    /// The OID of the table this column comes from, or zero if it is not a
+    /// table column.
+    pub fn table_oid(&self) -> u32 {
+        self.table_oid
+    }
+
+    /// The attribute number of the column within its table, or zero if it
+    /// is not a table column.
+    pub fn column_id(&self) -> i16 {
+        self.column_id
+    }
+
+    pub fn type_oid(&self) -> u32 {
+        self.type_oid
+    }
+
+    /// The size of the data type, as in `pg_type.typlen`.  Negative values
+    /// denote variable-width types.
+    pub fn type_size(&self) -> i16 {
+        self.type_size
+    }
+
+    /// The type modifier, as in `pg_attribute.atttypmod`.  Its meaning is
+    /// type-specific.
+    pub fn type_modifier(&self) -> i32 {
+        self.type_modifier
+    }
+
+    pub fn format(&self) -> FieldFormat {
+        self.format
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -350,7 +626,7 @@ impl<'a> NoticeBody<'a> {
 
         let mut parts: HashMap<char, &str> = HashMap::new();
         let mut more = Vec::new();
-        while bytes.get(0) != Some(&0) {
+        while bytes.first() != Some(&0) {
             let indicator = bytes[0].into();
             let (msg, _, end) = take_cstring_plus_fixed(&bytes[1..], 0).unwrap();
             if ['S', 'V', 'C', 'M', 'D', 'H', 'P', 'p', 'q'].contains(&indicator) {
@@ -387,9 +663,45 @@ impl<'a> NoticeBody<'a> {
         })
     }
 
+    /// The severity, possibly translated into the server's language.
+    pub fn severity_localized(&self) -> &str {
+        self.severity_loc
+    }
+
+    /// The untranslated severity, which servers before 9.6 do not send.
+    pub fn severity(&self) -> Option<Severity> {
+        self.severity
+    }
+
+    /// The SQLSTATE code of the error or notice.
+    pub fn code(&self) -> &str {
+        self.code
+    }
+
     pub fn message(&self) -> &str {
         self.message
     }
+
+    pub fn detail(&self) -> Option<&str> {
+        self.detail
+    }
+
+    pub fn hint(&self) -> Option<&str> {
+        self.hint
+    }
+
+    pub fn position(&self) -> Option<&Position<'a>> {
+        self.position.as_ref()
+    }
+
+    /// Any other field, by its type byte: for example `'n'` for the
+    /// constraint name, or `'L'` for the source line.
+    pub fn field(&self, indicator: char) -> Option<&str> {
+        self.more
+            .iter()
+            .find(|(field, _)| *field == indicator)
+            .map(|(_, value)| *value)
+    }
 }
 
 #[cfg(test)]
@@ -482,7 +794,7 @@ mod tests {
 
         let (next, buffer) = take_msg(buffer).unwrap();
         let msg = ServerMsg::from_slice(next).unwrap();
-        assert_eq!(msg, ServerMsg::ReadyForQuery);
+        assert_eq!(msg, ServerMsg::ReadyForQuery(TransactionStatus::Idle));
         assert_eq!(buffer.len(), 0);
 
         assert!(take_msg(buffer).is_err())
@@ -498,6 +810,11 @@ mod tests {
             msg,
             ServerMsg::RowDescription(vec![FieldDescription {
                 field_name: "version",
+                table_oid: 0,
+                column_id: 0,
+                type_oid: 25,
+                type_size: -1,
+                type_modifier: -1,
                 format: FieldFormat::Text,
             }])
         );
@@ -505,7 +822,7 @@ mod tests {
 
         let (next, buffer) = take_msg(buffer).unwrap();
         let msg = ServerMsg::from_slice(next).unwrap();
-        assert_eq!(msg, ServerMsg::DataRow(vec!["PostgreSQL 9.6.1 on x86_64-pc-linux-gnu, compiled by gcc (GCC) 6.2.1 20160830, 64-bit"]));
+        assert_eq!(msg, ServerMsg::DataRow(DataRow::new(vec![Some(&b"PostgreSQL 9.6.1 on x86_64-pc-linux-gnu, compiled by gcc (GCC) 6.2.1 20160830, 64-bit"[..])])));
         assert_eq!(buffer.len(), 20);
 
         let (next, buffer) = take_msg(buffer).unwrap();
@@ -515,7 +832,255 @@ mod tests {
 
         let (next, buffer) = take_msg(buffer).unwrap();
         let msg = ServerMsg::from_slice(next).unwrap();
-        assert_eq!(msg, ServerMsg::ReadyForQuery);
+        assert_eq!(msg, ServerMsg::ReadyForQuery(TransactionStatus::Idle));
         assert_eq!(buffer.len(), 0);
     }
+
+    fn frame(id: u8, body: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![id];
+        bytes.extend(&(body.len() as u32 + 4).to_be_bytes());
+        bytes.extend(body);
+        bytes
+    }
+
+    #[test]
+    fn test_empty_messages() {
+        let cases = [
+            (b'1', ServerMsg::ParseComplete),
+            (b'2', ServerMsg::BindComplete),
+            (b'3', ServerMsg::CloseComplete),
+            (b'n', ServerMsg::NoData),
+            (b's', ServerMsg::PortalSuspended),
+            (b'I', ServerMsg::EmptyQueryResponse),
+            (b'c', ServerMsg::CopyDone),
+        ];
+        for (id, expected) in cases.iter() {
+            let bytes = frame(*id, b"");
+            assert_eq!(&ServerMsg::from_slice(&bytes).unwrap(), expected);
+            assert!(ServerMsg::from_slice(&frame(*id, b"x")).is_err());
+        }
+    }
+
+    #[test]
+    fn test_error_response_fields() {
+        let bytes = frame(
+            b'E',
+            b"SERREUR\0VERROR\0C23505\0Mduplicate key\0DKey (id)=(1) already exists.\0P12\0tusers\0nusers_pkey\0L667\0\0",
+        );
+        let body = match ServerMsg::from_slice(&bytes).unwrap() {
+            ServerMsg::ErrorResponse(body) => body,
+            other => panic!("Expected ErrorResponse, got {:?}", other),
+        };
+        assert_eq!(body.severity_localized(), "ERREUR");
+        assert_eq!(body.severity(), Some(Severity::Error));
+        assert_eq!(body.code(), "23505");
+        assert_eq!(body.message(), "duplicate key");
+        assert_eq!(body.detail(), Some("Key (id)=(1) already exists."));
+        assert_eq!(body.hint(), None);
+        assert_eq!(body.position(), Some(&Position::Public(12)));
+        assert_eq!(body.field('t'), Some("users"));
+        assert_eq!(body.field('n'), Some("users_pkey"));
+        assert_eq!(body.field('L'), Some("667"));
+        assert_eq!(body.field('s'), None);
+    }
+
+    #[test]
+    fn test_parameter_description() {
+        let bytes = frame(b't', b"\x00\x02\x00\x00\x00\x17\x00\x00\x00\x19");
+        assert_eq!(
+            ServerMsg::from_slice(&bytes).unwrap(),
+            ServerMsg::ParameterDescription(vec![23, 25])
+        );
+        let bytes = frame(b't', b"\x00\x02\x00\x00\x00\x17");
+        assert!(ServerMsg::from_slice(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_copy_responses() {
+        let body = b"\x00\x00\x02\x00\x00\x00\x00";
+        let expected = CopyResponse {
+            format: FieldFormat::Text,
+            column_formats: vec![FieldFormat::Text, FieldFormat::Text],
+        };
+        assert_eq!(
+            ServerMsg::from_slice(&frame(b'G', body)).unwrap(),
+            ServerMsg::CopyInResponse(expected.clone())
+        );
+        assert_eq!(
+            ServerMsg::from_slice(&frame(b'H', body)).unwrap(),
+            ServerMsg::CopyOutResponse(expected.clone())
+        );
+        assert_eq!(
+            ServerMsg::from_slice(&frame(b'W', body)).unwrap(),
+            ServerMsg::CopyBothResponse(expected)
+        );
+
+        let body = b"\x01\x00\x01\x00\x01";
+        assert_eq!(
+            ServerMsg::from_slice(&frame(b'H', body)).unwrap(),
+            ServerMsg::CopyOutResponse(CopyResponse {
+                format: FieldFormat::Binary,
+                column_formats: vec![FieldFormat::Binary],
+            })
+        );
+        assert!(ServerMsg::from_slice(&frame(b'H', b"\x02\x00\x00")).is_err());
+    }
+
+    #[test]
+    fn test_copy_data() {
+        let bytes = frame(b'd', b"1\tuman\t42\n");
+        assert_eq!(
+            ServerMsg::from_slice(&bytes).unwrap(),
+            ServerMsg::CopyData(b"1\tuman\t42\n")
+        );
+    }
+
+    #[test]
+    fn test_notification_response() {
+        let bytes = frame(b'A', b"\x00\x00\x17\xbbjobs\x00new job\x00");
+        assert_eq!(
+            ServerMsg::from_slice(&bytes).unwrap(),
+            ServerMsg::NotificationResponse(6075, "jobs", "new job")
+        );
+    }
+
+    #[test]
+    fn test_function_call_response() {
+        let bytes = frame(b'V', b"\x00\x00\x00\x02ok");
+        assert_eq!(
+            ServerMsg::from_slice(&bytes).unwrap(),
+            ServerMsg::FunctionCallResponse(Some(b"ok"))
+        );
+        let bytes = frame(b'V', b"\xff\xff\xff\xff");
+        assert_eq!(
+            ServerMsg::from_slice(&bytes).unwrap(),
+            ServerMsg::FunctionCallResponse(None)
+        );
+        let bytes = frame(b'V', b"\x00\x00\x00\x03ok");
+        assert!(ServerMsg::from_slice(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_negotiate_protocol_version() {
+        let bytes = frame(b'v', b"\x00\x00\x00\x00\x00\x00\x00\x01_pq_.frobnicate\x00");
+        assert_eq!(
+            ServerMsg::from_slice(&bytes).unwrap(),
+            ServerMsg::NegotiateProtocolVersion(0, vec!["_pq_.frobnicate"])
+        );
+    }
+
+    #[test]
+    fn test_data_row_with_nulls() {
+        let bytes = frame(
+            b'D',
+            b"\x00\x03\x00\x00\x00\x02hi\xff\xff\xff\xff\x00\x00\x00\x00",
+        );
+        let msg = ServerMsg::from_slice(&bytes).unwrap();
+        let row = match msg {
+            ServerMsg::DataRow(row) => row,
+            msg => panic!("unexpected message {:?}", msg),
+        };
+        assert_eq!(row.len(), 3);
+        assert_eq!(row.cell(0), Some(&b"hi"[..]));
+        assert_eq!(row.cell(1), None);
+        assert_eq!(row.cell(2), Some(&b""[..]));
+        assert_eq!(row.texts().unwrap(), vec![Some("hi"), None, Some("")]);
+        assert!(row.text(3).is_err());
+    }
+
+    #[test]
+    fn test_data_row_with_binary() {
+        let bytes = frame(b'D', b"\x00\x01\x00\x00\x00\x02\xff\xfe");
+        let msg = ServerMsg::from_slice(&bytes).unwrap();
+        assert_eq!(
+            msg,
+            ServerMsg::DataRow(DataRow::new(vec![Some(&b"\xff\xfe"[..])]))
+        );
+        if let ServerMsg::DataRow(row) = msg {
+            assert!(row.text(0).is_err());
+        }
+    }
+
+    #[test]
+    fn test_data_row_truncated() {
+        let bytes = frame(b'D', b"\x00\x01\x00\x00\x00\x05abc");
+        assert!(ServerMsg::from_slice(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_row_description_metadata() {
+        let bytes = frame(
+            b'T',
+            b"\x00\x02id\x00\x00\x00\x40\x01\x00\x01\x00\x00\x00\x17\x00\x04\xff\xff\xff\xff\x00\x01\
+              name\x00\x00\x00\x40\x01\x00\x02\x00\x00\x04\x13\xff\xff\x00\x00\x00\x24\x00\x00",
+        );
+        let fields = match ServerMsg::from_slice(&bytes).unwrap() {
+            ServerMsg::RowDescription(fields) => fields,
+            msg => panic!("unexpected message {:?}", msg),
+        };
+        assert_eq!(fields.len(), 2);
+
+        assert_eq!(fields[0].name(), "id");
+        assert_eq!(fields[0].table_oid(), 16385);
+        assert_eq!(fields[0].column_id(), 1);
+        assert_eq!(fields[0].type_oid(), 23);
+        assert_eq!(fields[0].type_size(), 4);
+        assert_eq!(fields[0].type_modifier(), -1);
+        assert_eq!(fields[0].format(), FieldFormat::Binary);
+
+        assert_eq!(fields[1].name(), "name");
+        assert_eq!(fields[1].column_id(), 2);
+        assert_eq!(fields[1].type_oid(), 1043);
+        assert_eq!(fields[1].type_size(), -1);
+        assert_eq!(fields[1].type_modifier(), 36);
+        assert_eq!(fields[1].format(), FieldFormat::Text);
+    }
+
+    #[test]
+    fn test_row_description_truncated() {
+        let bytes = frame(b'T', b"\x00\x01id\x00\x00\x00\x40\x01");
+        assert!(ServerMsg::from_slice(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_ready_for_query_status() {
+        let cases = [
+            (b'I', TransactionStatus::Idle),
+            (b'T', TransactionStatus::InTransaction),
+            (b'E', TransactionStatus::Failed),
+        ];
+        for (status, expected) in cases.iter() {
+            let bytes = frame(b'Z', &[*status]);
+            assert_eq!(
+                ServerMsg::from_slice(&bytes).unwrap(),
+                ServerMsg::ReadyForQuery(*expected)
+            );
+        }
+        assert!(ServerMsg::from_slice(&frame(b'Z', b"X")).is_err());
+        assert!(ServerMsg::from_slice(&frame(b'Z', b"")).is_err());
+    }
+
+    #[test]
+    fn test_sasl_auth_messages() {
+        let bytes = frame(
+            b'R',
+            b"\x00\x00\x00\x0aSCRAM-SHA-256-PLUS\x00SCRAM-SHA-256\x00\x00",
+        );
+        assert_eq!(
+            ServerMsg::from_slice(&bytes).unwrap(),
+            ServerMsg::Auth(AuthMsg::Sasl(vec!["SCRAM-SHA-256-PLUS", "SCRAM-SHA-256"]))
+        );
+        let bytes = frame(b'R', b"\x00\x00\x00\x0br=abc,s=c2FsdA==,i=4096");
+        assert_eq!(
+            ServerMsg::from_slice(&bytes).unwrap(),
+            ServerMsg::Auth(AuthMsg::SaslContinue(b"r=abc,s=c2FsdA==,i=4096"))
+        );
+        let bytes = frame(b'R', b"\x00\x00\x00\x0cv=abc");
+        assert_eq!(
+            ServerMsg::from_slice(&bytes).unwrap(),
+            ServerMsg::Auth(AuthMsg::SaslFinal(b"v=abc"))
+        );
+        let bytes = frame(b'R', b"\x00\x00\x00\x0aSCRAM-SHA-256\x00");
+        assert!(ServerMsg::from_slice(&bytes).is_err());
+    }
 }
@@ -1,5 +1,13 @@
+use crate::{error::PgError, Result};
 use crypto::digest::Digest;
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
 use crypto::md5::Md5;
+use crypto::pbkdf2::pbkdf2;
+use crypto::sha2::{Sha256, Sha384, Sha512};
+use crypto::util::fixed_time_eq;
+use rand::Rng;
+use std::str::FromStr;
 
 pub fn build_md5_hash(user: &str, password: &str, salt: &[u8]) -> String {
     let mut userpasshasher = Md5::new();
@@ -16,6 +24,325 @@ pub fn build_md5_hash(user: &str, password: &str, salt: &[u8]) -> String {
     final_hash
 }
 
+pub const SCRAM_SHA_256: &str = "SCRAM-SHA-256";
+pub const SCRAM_SHA_256_PLUS: &str = "SCRAM-SHA-256-PLUS";
+
+/// Whether to use SCRAM channel binding, like libpq's `channel_binding`
+/// setting.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ChannelBinding {
+    /// Never use channel binding.
+    Disable,
+    /// Use channel binding if the connection is encrypted and the server
+    /// supports it.
+    #[default]
+    Prefer,
+    /// Fail unless the server authenticates with channel binding.
+    Require,
+}
+
+impl FromStr for ChannelBinding {
+    type Err = PgError;
+
+    fn from_str(s: &str) -> Result<ChannelBinding> {
+        match s {
+            "disable" => Ok(ChannelBinding::Disable),
+            "prefer" => Ok(ChannelBinding::Prefer),
+            "require" => Ok(ChannelBinding::Require),
+            _ => Err(PgError::Error(format!(
+                "Invalid channel_binding value: {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+/// The channel binding flag sent in the GS2 header of a SCRAM exchange.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ScramBinding {
+    /// The client does not support channel binding.
+    Unsupported,
+    /// The client supports channel binding, but the server did not offer
+    /// it.
+    NotAdvertised,
+    /// Bind to the hash of the server's TLS certificate.
+    TlsServerEndPoint(Vec<u8>),
+}
+
+impl ScramBinding {
+    fn gs2_header(&self) -> &'static str {
+        match self {
+            ScramBinding::Unsupported => "n,,",
+            ScramBinding::NotAdvertised => "y,,",
+            ScramBinding::TlsServerEndPoint(_) => "p=tls-server-end-point,,",
+        }
+    }
+
+    fn cbind_input(&self) -> Vec<u8> {
+        let mut input = self.gs2_header().as_bytes().to_vec();
+        if let ScramBinding::TlsServerEndPoint(data) = self {
+            input.extend(data);
+        }
+        input
+    }
+}
+
+/// Pick the SASL mechanism and channel binding to use, given the
+/// mechanisms offered by the server and the `tls-server-end-point` data of
+/// the connection, if it is encrypted.
+pub fn select_scram_mechanism(
+    mechanisms: &[&str],
+    channel_binding: ChannelBinding,
+    tls_server_end_point: Option<&[u8]>,
+) -> Result<(&'static str, ScramBinding)> {
+    let plus_offered = mechanisms.contains(&SCRAM_SHA_256_PLUS);
+    let plain_offered = mechanisms.contains(&SCRAM_SHA_256);
+    match (channel_binding, tls_server_end_point) {
+        (ChannelBinding::Disable, _) if plain_offered => {
+            Ok((SCRAM_SHA_256, ScramBinding::Unsupported))
+        }
+        (ChannelBinding::Prefer, Some(data)) | (ChannelBinding::Require, Some(data))
+            if plus_offered =>
+        {
+            Ok((
+                SCRAM_SHA_256_PLUS,
+                ScramBinding::TlsServerEndPoint(data.to_vec()),
+            ))
+        }
+        (ChannelBinding::Require, _) => Err(PgError::Error(
+            "channel_binding=require, but the server did not offer SCRAM-SHA-256-PLUS over TLS"
+                .to_string(),
+        )),
+        (ChannelBinding::Prefer, Some(_)) if plain_offered => {
+            Ok((SCRAM_SHA_256, ScramBinding::NotAdvertised))
+        }
+        (ChannelBinding::Prefer, None) if plain_offered => {
+            Ok((SCRAM_SHA_256, ScramBinding::Unsupported))
+        }
+        _ => Err(PgError::Error(format!(
+            "No supported SASL mechanism in {:?}",
+            mechanisms
+        ))),
+    }
+}
+
+/// Compute `tls-server-end-point` channel binding data (RFC 5929) from the
+/// server's DER-encoded certificate: a hash of the certificate using the
+/// hash function from its signature algorithm, with MD5 and SHA-1 replaced
+/// by SHA-256.
+pub fn tls_server_end_point(certificate: &[u8]) -> Vec<u8> {
+    const SHA384_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0c];
+    const SHA512_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0d];
+    const ECDSA_WITH_SHA384: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03];
+    const ECDSA_WITH_SHA512: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x04];
+
+    let mut hasher: Box<dyn Digest> = match signature_algorithm(certificate) {
+        Some(oid) if oid == SHA384_WITH_RSA || oid == ECDSA_WITH_SHA384 => Box::new(Sha384::new()),
+        Some(oid) if oid == SHA512_WITH_RSA || oid == ECDSA_WITH_SHA512 => Box::new(Sha512::new()),
+        _ => Box::new(Sha256::new()),
+    };
+    hasher.input(certificate);
+    let mut out = vec![0; hasher.output_bytes()];
+    hasher.result(&mut out);
+    out
+}
+
+/// Split the DER element at the front of `input` into its tag, contents
+/// and the remaining input.
+fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (length, rest) = if first & 0x80 == 0 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let length = rest[..count]
+            .iter()
+            .fold(0, |acc, &byte| (acc << 8) | byte as usize);
+        (length, &rest[count..])
+    };
+    if rest.len() < length {
+        return None;
+    }
+    Some((tag, &rest[..length], &rest[length..]))
+}
+
+/// The signature algorithm OID of an X.509 certificate.
+fn signature_algorithm(certificate: &[u8]) -> Option<&[u8]> {
+    const OID: u8 = 0x06;
+    let (_, certificate, _) = der_element(certificate)?;
+    let (_, _tbs_certificate, rest) = der_element(certificate)?;
+    let (_, algorithm, _) = der_element(rest)?;
+    match der_element(algorithm)? {
+        (OID, oid, _) => Some(oid),
+        _ => None,
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::new(Sha256::new(), key);
+    mac.input(data);
+    let mut out = [0; 32];
+    out.copy_from_slice(mac.result().code());
+    out
+}
+
+fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.input(data);
+    let mut out = [0; 32];
+    hasher.result(&mut out);
+    out
+}
+
+fn scram_error(message: &str) -> PgError {
+    PgError::Error(format!("SCRAM authentication failed: {}", message))
+}
+
+#[derive(Debug)]
+enum ScramState {
+    Initial,
+    ClientFinalSent { server_signature: [u8; 32] },
+    Complete,
+}
+
+/// Client side of a SCRAM-SHA-256 exchange (RFC 5802, RFC 7677).
+///
+/// Postgres takes the user name from the startup message, so the name sent
+/// in the SCRAM messages is left empty by [`ScramSha256::new`].  The
+/// password is used as-is, without SASLprep normalization, which matches
+/// what the server does for ASCII passwords.
+#[derive(Debug)]
+pub struct ScramSha256 {
+    password: String,
+    binding: ScramBinding,
+    nonce: String,
+    client_first_bare: String,
+    state: ScramState,
+}
+
+impl ScramSha256 {
+    pub fn new(password: &str, binding: ScramBinding) -> ScramSha256 {
+        let nonce: [u8; 18] = rand::thread_rng().gen();
+        ScramSha256::with_nonce("", password, &base64::encode(nonce), binding)
+    }
+
+    pub fn with_nonce(
+        user: &str,
+        password: &str,
+        nonce: &str,
+        binding: ScramBinding,
+    ) -> ScramSha256 {
+        let user = user.replace('=', "=3D").replace(',', "=2C");
+        ScramSha256 {
+            password: password.to_string(),
+            binding,
+            nonce: nonce.to_string(),
+            client_first_bare: format!("n={},r={}", user, nonce),
+            state: ScramState::Initial,
+        }
+    }
+
+    /// True if the server is bound to the TLS channel by this exchange.
+    pub fn uses_channel_binding(&self) -> bool {
+        matches!(self.binding, ScramBinding::TlsServerEndPoint(_))
+    }
+
+    /// The client-first-message, sent in the SASLInitialResponse.
+    pub fn client_first_message(&self) -> Vec<u8> {
+        format!("{}{}", self.binding.gs2_header(), self.client_first_bare).into_bytes()
+    }
+
+    /// Process the server-first-message, and return the client-final-message
+    /// to send in response.
+    pub fn handle_server_first(&mut self, message: &[u8]) -> Result<Vec<u8>> {
+        if !matches!(self.state, ScramState::Initial) {
+            return Err(scram_error("unexpected server-first-message"));
+        }
+        let server_first = std::str::from_utf8(message)?;
+        let mut nonce = None;
+        let mut salt = None;
+        let mut iterations = None;
+        for attr in server_first.split(',') {
+            match attr.split_at(attr.find('=').map(|i| i + 1).unwrap_or(0)) {
+                ("r=", value) => nonce = Some(value),
+                ("s=", value) => {
+                    salt = Some(base64::decode(value).map_err(|_| scram_error("invalid salt"))?)
+                }
+                ("i=", value) => iterations = Some(value.parse::<u32>()?),
+                ("m=", _) => return Err(scram_error("unsupported mandatory extension")),
+                _ => {}
+            }
+        }
+        let nonce = nonce.ok_or_else(|| scram_error("missing nonce"))?;
+        let salt = salt.ok_or_else(|| scram_error("missing salt"))?;
+        let iterations = iterations.ok_or_else(|| scram_error("missing iteration count"))?;
+        if !nonce.starts_with(&self.nonce) || nonce.len() == self.nonce.len() {
+            return Err(scram_error("invalid server nonce"));
+        }
+        if iterations == 0 {
+            return Err(scram_error("invalid iteration count"));
+        }
+
+        let mut salted_password = [0; 32];
+        let mut mac = Hmac::new(Sha256::new(), self.password.as_bytes());
+        pbkdf2(&mut mac, &salt, iterations, &mut salted_password);
+        let client_key = hmac_sha256(&salted_password, b"Client Key");
+        let stored_key = sha256(&client_key);
+        let server_key = hmac_sha256(&salted_password, b"Server Key");
+
+        let client_final_without_proof = format!(
+            "c={},r={}",
+            base64::encode(self.binding.cbind_input()),
+            nonce
+        );
+        let auth_message = format!(
+            "{},{},{}",
+            self.client_first_bare, server_first, client_final_without_proof
+        );
+        let client_signature = hmac_sha256(&stored_key, auth_message.as_bytes());
+        let mut proof = client_key;
+        for (byte, sig) in proof.iter_mut().zip(client_signature.iter()) {
+            *byte ^= sig;
+        }
+        self.state = ScramState::ClientFinalSent {
+            server_signature: hmac_sha256(&server_key, auth_message.as_bytes()),
+        };
+        Ok(format!("{},p={}", client_final_without_proof, base64::encode(proof)).into_bytes())
+    }
+
+    /// Check the server-final-message.  This proves that the server knows
+    /// the password too.
+    pub fn verify_server_final(&mut self, message: &[u8]) -> Result<()> {
+        let expected = match self.state {
+            ScramState::ClientFinalSent { server_signature } => server_signature,
+            _ => return Err(scram_error("unexpected server-final-message")),
+        };
+        let server_final = std::str::from_utf8(message)?;
+        if let Some(err) = server_final.strip_prefix("e=") {
+            return Err(scram_error(err));
+        }
+        let verifier = server_final
+            .split(',')
+            .find_map(|attr| attr.strip_prefix("v="))
+            .ok_or_else(|| scram_error("missing server signature"))?;
+        let signature =
+            base64::decode(verifier).map_err(|_| scram_error("invalid server signature"))?;
+        if !fixed_time_eq(&signature, &expected) {
+            return Err(scram_error("server signature does not match"));
+        }
+        self.state = ScramState::Complete;
+        Ok(())
+    }
+
+    /// True once the server's signature has been verified.
+    pub fn is_complete(&self) -> bool {
+        matches!(self.state, ScramState::Complete)
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -23,6 +350,172 @@ mod tests {
 
     #[test]
     fn test_md5_hash() {
-        assert_eq!(build_md5_hash("", "", b"abcd"), "md5743b08b8561cc75c4f899c35d6c3c3eb");
+        assert_eq!(
+            build_md5_hash("", "", b"abcd"),
+            "md5743b08b8561cc75c4f899c35d6c3c3eb"
+        );
+    }
+
+    // Test vectors from RFC 7677, section 3.
+    const CLIENT_NONCE: &str = "rOprNGfwEbeRWgbNEkqO";
+    const SERVER_FIRST: &[u8] =
+        b"r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096";
+    const CLIENT_FINAL: &[u8] = b"c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=";
+    const SERVER_FINAL: &[u8] = b"v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=";
+
+    #[test]
+    fn test_scram_sha_256_rfc7677() {
+        let mut scram =
+            ScramSha256::with_nonce("user", "pencil", CLIENT_NONCE, ScramBinding::Unsupported);
+        assert_eq!(
+            scram.client_first_message(),
+            b"n,,n=user,r=rOprNGfwEbeRWgbNEkqO".to_vec()
+        );
+        assert_eq!(
+            scram.handle_server_first(SERVER_FIRST).unwrap(),
+            CLIENT_FINAL
+        );
+        assert!(!scram.is_complete());
+        scram.verify_server_final(SERVER_FINAL).unwrap();
+        assert!(scram.is_complete());
+    }
+
+    #[test]
+    fn test_scram_bad_server_signature() {
+        let mut scram =
+            ScramSha256::with_nonce("user", "pencil", CLIENT_NONCE, ScramBinding::Unsupported);
+        scram.handle_server_first(SERVER_FIRST).unwrap();
+        assert!(scram
+            .verify_server_final(b"v=AAAATRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=")
+            .is_err());
+        assert!(!scram.is_complete());
+    }
+
+    #[test]
+    fn test_scram_wrong_password() {
+        let mut scram =
+            ScramSha256::with_nonce("user", "pencils", CLIENT_NONCE, ScramBinding::Unsupported);
+        assert_ne!(
+            scram.handle_server_first(SERVER_FIRST).unwrap(),
+            CLIENT_FINAL
+        );
+        assert!(scram.verify_server_final(SERVER_FINAL).is_err());
+    }
+
+    #[test]
+    fn test_scram_rejects_bad_server_nonce() {
+        let mut scram =
+            ScramSha256::with_nonce("user", "pencil", CLIENT_NONCE, ScramBinding::Unsupported);
+        assert!(scram
+            .handle_server_first(b"r=somebodyelse,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096")
+            .is_err());
+        let mut scram =
+            ScramSha256::with_nonce("user", "pencil", CLIENT_NONCE, ScramBinding::Unsupported);
+        assert!(scram
+            .handle_server_first(b"r=rOprNGfwEbeRWgbNEkqO,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096")
+            .is_err());
+    }
+
+    #[test]
+    fn test_scram_server_error() {
+        let mut scram =
+            ScramSha256::with_nonce("user", "pencil", CLIENT_NONCE, ScramBinding::Unsupported);
+        scram.handle_server_first(SERVER_FIRST).unwrap();
+        assert!(scram.verify_server_final(b"e=invalid-proof").is_err());
+    }
+
+    #[test]
+    fn test_scram_random_nonce() {
+        let scram = ScramSha256::new("pencil", ScramBinding::Unsupported);
+        let first = String::from_utf8(scram.client_first_message()).unwrap();
+        assert!(first.starts_with("n,,n=,r="));
+        assert_eq!(first.len(), "n,,n=,r=".len() + 24);
+    }
+
+    #[test]
+    fn test_scram_channel_binding_headers() {
+        let binding = ScramBinding::TlsServerEndPoint(vec![1, 2, 3]);
+        let mut scram = ScramSha256::with_nonce("user", "pencil", CLIENT_NONCE, binding);
+        assert!(scram.uses_channel_binding());
+        assert_eq!(
+            scram.client_first_message(),
+            b"p=tls-server-end-point,,n=user,r=rOprNGfwEbeRWgbNEkqO".to_vec()
+        );
+        let client_final = scram.handle_server_first(SERVER_FIRST).unwrap();
+        let expected = format!(
+            "c={},r=",
+            base64::encode(b"p=tls-server-end-point,,\x01\x02\x03")
+        );
+        assert!(client_final.starts_with(expected.as_bytes()));
+        // The binding is part of the signed AuthMessage.
+        assert!(scram.verify_server_final(SERVER_FINAL).is_err());
+
+        let mut scram =
+            ScramSha256::with_nonce("user", "pencil", CLIENT_NONCE, ScramBinding::NotAdvertised);
+        assert!(!scram.uses_channel_binding());
+        assert!(scram.client_first_message().starts_with(b"y,,n=user"));
+        assert!(scram
+            .handle_server_first(SERVER_FIRST)
+            .unwrap()
+            .starts_with(b"c=eSws,"));
+    }
+
+    #[test]
+    fn test_select_scram_mechanism() {
+        let both = [SCRAM_SHA_256_PLUS, SCRAM_SHA_256];
+        let plain = [SCRAM_SHA_256];
+        let data = Some(&[1u8, 2, 3][..]);
+        let tls_binding = ScramBinding::TlsServerEndPoint(vec![1, 2, 3]);
+
+        assert_eq!(
+            select_scram_mechanism(&both, ChannelBinding::Prefer, data).unwrap(),
+            (SCRAM_SHA_256_PLUS, tls_binding.clone())
+        );
+        assert_eq!(
+            select_scram_mechanism(&both, ChannelBinding::Require, data).unwrap(),
+            (SCRAM_SHA_256_PLUS, tls_binding)
+        );
+        assert_eq!(
+            select_scram_mechanism(&both, ChannelBinding::Disable, data).unwrap(),
+            (SCRAM_SHA_256, ScramBinding::Unsupported)
+        );
+        assert_eq!(
+            select_scram_mechanism(&plain, ChannelBinding::Prefer, data).unwrap(),
+            (SCRAM_SHA_256, ScramBinding::NotAdvertised)
+        );
+        assert_eq!(
+            select_scram_mechanism(&plain, ChannelBinding::Prefer, None).unwrap(),
+            (SCRAM_SHA_256, ScramBinding::Unsupported)
+        );
+        assert!(select_scram_mechanism(&plain, ChannelBinding::Require, data).is_err());
+        assert!(select_scram_mechanism(&both, ChannelBinding::Require, None).is_err());
+        assert!(select_scram_mechanism(&["OTHER"], ChannelBinding::Prefer, None).is_err());
+        assert_eq!(
+            "require".parse::<ChannelBinding>().unwrap(),
+            ChannelBinding::Require
+        );
+        assert!("sometimes".parse::<ChannelBinding>().is_err());
+    }
+
+    #[test]
+    fn test_tls_server_end_point_hash() {
+        // A skeleton certificate: an empty TBSCertificate, the signature
+        // algorithm, and an empty signature.
+        let sha384_cert =
+            b"\x30\x12\x30\x00\x30\x0b\x06\x09\x2a\x86\x48\x86\xf7\x0d\x01\x01\x0c\x03\x01\x00";
+        let mut hasher = Sha384::new();
+        hasher.input(sha384_cert);
+        let mut expected = vec![0; 48];
+        hasher.result(&mut expected);
+        assert_eq!(tls_server_end_point(sha384_cert), expected);
+
+        // SHA-1 signatures are hashed with SHA-256.
+        let sha1_cert =
+            b"\x30\x12\x30\x00\x30\x0b\x06\x09\x2a\x86\x48\x86\xf7\x0d\x01\x01\x05\x03\x01\x00";
+        assert_eq!(tls_server_end_point(sha1_cert), sha256(sha1_cert).to_vec());
+        assert_eq!(
+            tls_server_end_point(b"garbage"),
+            sha256(b"garbage").to_vec()
+        );
     }
 }
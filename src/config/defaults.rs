@@ -0,0 +1,245 @@
+//! Filling in settings that were not given explicitly, the way libpq does:
+//! first from the service file, then from `PG*` environment variables, and
+//! finally from built-in defaults.
+
+use super::{service, Config};
+use crate::Result;
+use std::path::PathBuf;
+
+/// Environment variables and the parameters they provide defaults for.
+const ENV_VARS: &[(&str, &str)] = &[
+    ("PGHOST", "host"),
+    ("PGPORT", "port"),
+    ("PGDATABASE", "dbname"),
+    ("PGUSER", "user"),
+    ("PGPASSWORD", "password"),
+    ("PGPASSFILE", "passfile"),
+    ("PGOPTIONS", "options"),
+    ("PGAPPNAME", "application_name"),
+    ("PGSSLMODE", "sslmode"),
+    ("PGSSLROOTCERT", "sslrootcert"),
+    ("PGSSLCERT", "sslcert"),
+    ("PGSSLKEY", "sslkey"),
+    ("PGCHANNELBINDING", "channel_binding"),
+    ("PGCONNECT_TIMEOUT", "connect_timeout"),
+    ("PGCLIENTENCODING", "client_encoding"),
+    ("PGTARGETSESSIONATTRS", "target_session_attrs"),
+    ("PGGSSENCMODE", "gssencmode"),
+];
+
+/// Environment variables that set server parameters in the startup message.
+const ENV_PARAMS: &[(&str, &str)] = &[
+    ("PGDATESTYLE", "datestyle"),
+    ("PGTZ", "timezone"),
+    ("PGGEQO", "geqo"),
+];
+
+/// Where to look for the per-user files, `.pgpass` and `.pg_service.conf`.
+pub(super) fn home_dir(env: &dyn Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    if cfg!(windows) {
+        env("APPDATA").map(|appdata| PathBuf::from(appdata).join("postgresql"))
+    } else {
+        env("HOME").map(PathBuf::from)
+    }
+}
+
+impl Config {
+    /// Whether a libpq parameter has been set, so that defaults leave it
+    /// alone.
+    fn is_set(&self, key: &str) -> bool {
+        match key {
+            "host" => !self.hosts.is_empty(),
+            "port" => !self.ports.is_empty(),
+            "user" => self.user.is_some(),
+            "password" => self.password.is_some(),
+            "dbname" => self.dbname.is_some(),
+            "application_name" => self.application_name.is_some(),
+            "fallback_application_name" => self.fallback_application_name.is_some(),
+            "options" => self.options.is_some(),
+            "connect_timeout" => self.connect_timeout.is_some(),
+            "keepalives_idle" => self.keepalives_idle.is_some(),
+            "keepalives_interval" => self.keepalives_interval.is_some(),
+            "keepalives_count" => self.keepalives_count.is_some(),
+            "sslmode" => self.ssl_mode.is_some(),
+            "sslrootcert" => self.ssl_root_cert.is_some(),
+            "sslcert" => self.ssl_cert.is_some(),
+            "sslkey" => self.ssl_key.is_some(),
+            "channel_binding" => self.channel_binding.is_some(),
+            "target_session_attrs" => self.target_session_attrs.is_some(),
+            "passfile" => self.passfile.is_some(),
+            key => self
+                .params
+                .iter()
+                .any(|(name, _)| name.eq_ignore_ascii_case(key)),
+        }
+    }
+
+    /// Apply a libpq parameter unless it has already been set.
+    pub(super) fn set_default(&mut self, key: &str, value: &str) -> Result<()> {
+        if self.is_set(key) {
+            Ok(())
+        } else {
+            self.set(key, value)
+        }
+    }
+
+    /// A copy of this config with every unset parameter filled in from the
+    /// service file, the environment, and built-in defaults.  `env` looks
+    /// up environment variables.
+    pub(crate) fn with_defaults(&self, env: &dyn Fn(&str) -> Option<String>) -> Result<Config> {
+        let mut config = self.clone();
+
+        if let Some(service) = config.service.clone().or_else(|| env("PGSERVICE")) {
+            service::apply(&mut config, &service, env)?;
+        }
+
+        for (var, key) in ENV_VARS {
+            if let Some(value) = env(var).filter(|value| !value.is_empty()) {
+                config.set_default(key, &value)?;
+            }
+        }
+        for (var, name) in ENV_PARAMS {
+            if let Some(value) = env(var).filter(|value| !value.is_empty()) {
+                if !config.is_set(name) {
+                    config.param(name, &value);
+                }
+            }
+        }
+
+        if config.user.is_none() {
+            // libpq uses the operating system user name.
+            if let Some(user) = ["USER", "LOGNAME", "USERNAME"]
+                .iter()
+                .find_map(|var| env(var))
+            {
+                config.user(&user);
+            }
+        }
+        if config.passfile.is_none() {
+            let name = if cfg!(windows) {
+                "pgpass.conf"
+            } else {
+                ".pgpass"
+            };
+            if let Some(home) = home_dir(env) {
+                config.passfile(home.join(name));
+            }
+        }
+        #[cfg(feature = "tls")]
+        if let Some(home) = home_dir(env) {
+            // As in libpq, certificates in ~/.postgresql are used if they
+            // exist.
+            let dir = if cfg!(windows) {
+                home
+            } else {
+                home.join(".postgresql")
+            };
+            if config.ssl_root_cert.is_none() && dir.join("root.crt").is_file() {
+                config.ssl_root_cert(dir.join("root.crt"));
+            }
+            let (cert, key) = (dir.join("postgresql.crt"), dir.join("postgresql.key"));
+            if config.ssl_cert.is_none()
+                && config.ssl_key.is_none()
+                && cert.is_file()
+                && key.is_file()
+            {
+                config.ssl_cert(cert).ssl_key(key);
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tls::SslMode;
+    use std::{collections::HashMap, time::Duration};
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_env_defaults() {
+        let env = env(&[
+            ("PGHOST", "db1,db2"),
+            ("PGPORT", "5433"),
+            ("PGUSER", "gres"),
+            ("PGDATABASE", "reports"),
+            ("PGPASSWORD", "secret"),
+            ("PGSSLMODE", "verify-full"),
+            ("PGAPPNAME", "nightly"),
+            ("PGCONNECT_TIMEOUT", "10"),
+            ("PGTZ", "UTC"),
+            ("PGOPTIONS", ""),
+            ("HOME", "/home/gres"),
+        ]);
+        let config = Config::new().with_defaults(&env).unwrap();
+        assert_eq!(config.get_hosts(), ["db1", "db2"]);
+        assert_eq!(config.get_ports(), [5433]);
+        assert_eq!(config.get_user(), Some("gres"));
+        assert_eq!(config.get_dbname(), Some("reports"));
+        assert_eq!(config.get_password(), Some("secret"));
+        assert_eq!(config.get_ssl_mode(), SslMode::VerifyFull);
+        assert_eq!(config.get_application_name(), Some("nightly"));
+        assert_eq!(config.get_connect_timeout(), Some(Duration::from_secs(10)));
+        assert_eq!(config.get_options(), None);
+        assert_eq!(
+            config.get_params(),
+            [("timezone".to_string(), "UTC".to_string())]
+        );
+        if !cfg!(windows) {
+            assert_eq!(
+                config.get_passfile(),
+                Some(std::path::Path::new("/home/gres/.pgpass"))
+            );
+        }
+    }
+
+    #[test]
+    fn test_explicit_settings_win() {
+        let env = env(&[
+            ("PGHOST", "elsewhere"),
+            ("PGUSER", "someone"),
+            ("PGSSLMODE", "disable"),
+            ("PGTZ", "UTC"),
+        ]);
+        let mut config: Config = "host=db user=gres sslmode=require".parse().unwrap();
+        config.param("TimeZone", "Europe/Paris");
+        let config = config.with_defaults(&env).unwrap();
+        assert_eq!(config.get_hosts(), ["db"]);
+        assert_eq!(config.get_user(), Some("gres"));
+        assert_eq!(config.get_ssl_mode(), SslMode::Require);
+        assert_eq!(
+            config.get_params(),
+            [("TimeZone".to_string(), "Europe/Paris".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_os_user() {
+        let config = Config::new()
+            .with_defaults(&env(&[("USER", "gres")]))
+            .unwrap();
+        assert_eq!(config.get_user(), Some("gres"));
+        let config = Config::new()
+            .with_defaults(&env(&[("USER", "gres"), ("PGUSER", "other")]))
+            .unwrap();
+        assert_eq!(config.get_user(), Some("other"));
+    }
+
+    #[test]
+    fn test_invalid_env() {
+        assert!(Config::new()
+            .with_defaults(&env(&[("PGSSLMODE", "sometimes")]))
+            .is_err());
+        assert!(Config::new()
+            .with_defaults(&env(&[("PGPORT", "big")]))
+            .is_err());
+    }
+}
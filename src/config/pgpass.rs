@@ -0,0 +1,137 @@
+//! Password lookups in `~/.pgpass`.
+//!
+//! Each line of the file has the form
+//! `hostname:port:database:username:password`.  Any of the first four
+//! fields may be `*` to match anything, and `:` or `\` within a field are
+//! escaped with a backslash.
+
+use std::{fs, path::Path};
+
+/// The password for the first matching line of the password file at
+/// `path`.  A missing or unreadable file, or one that other users could
+/// read, yields `None`.
+pub(crate) fn lookup(
+    path: &Path,
+    host: &str,
+    port: u16,
+    dbname: &str,
+    user: &str,
+) -> Option<String> {
+    let metadata = fs::metadata(path).ok()?;
+    if !metadata.is_file() {
+        ::log::warn!("Password file {:?} is not a plain file", path);
+        return None;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o077 != 0 {
+            ::log::warn!(
+                "Password file {:?} has group or world access; permissions should be u=rw (0600) or less",
+                path
+            );
+            return None;
+        }
+    }
+    let contents = fs::read_to_string(path).ok()?;
+    find_password(&contents, host, &port.to_string(), dbname, user)
+}
+
+fn find_password(
+    contents: &str,
+    host: &str,
+    port: &str,
+    dbname: &str,
+    user: &str,
+) -> Option<String> {
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let fields = split_fields(line);
+            if fields.len() != 5 {
+                return None;
+            }
+            let matches = |field: &str, value: &str| field == "*" || field == value;
+            if matches(&fields[0], host)
+                && matches(&fields[1], port)
+                && matches(&fields[2], dbname)
+                && matches(&fields[3], user)
+            {
+                Some(fields[4].clone())
+            } else {
+                None
+            }
+        })
+        .next()
+}
+
+/// Split a line on unescaped colons, removing the escapes.  The password,
+/// being the last field, may contain unescaped colons.
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    fields.last_mut().unwrap().push(c);
+                }
+            }
+            ':' if fields.len() < 5 => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    const PGPASS: &str = "\
+# hostname:port:database:username:password
+db.example.com:5432:reports:analyst:s3cret
+db.example.com:*:*:analyst:fallback
+*:5433:*:*:with\\:colon:and more\\\\
+incomplete:line
+";
+
+    #[test]
+    fn test_find_password() {
+        let find = |host, port, dbname, user| find_password(PGPASS, host, port, dbname, user);
+        assert_eq!(
+            find("db.example.com", "5432", "reports", "analyst").as_deref(),
+            Some("s3cret")
+        );
+        assert_eq!(
+            find("db.example.com", "5432", "other", "analyst").as_deref(),
+            Some("fallback")
+        );
+        assert_eq!(
+            find("localhost", "5433", "db", "anyone").as_deref(),
+            Some("with:colon:and more\\")
+        );
+        assert_eq!(find("localhost", "5432", "db", "anyone"), None);
+        assert_eq!(find("incomplete", "line", "", ""), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = env::temp_dir().join(format!("gres-pgpass-{}", std::process::id()));
+        fs::write(&path, "*:*:*:gres:secret\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(
+            lookup(&path, "localhost", 5432, "gres", "gres").as_deref(),
+            Some("secret")
+        );
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert_eq!(lookup(&path, "localhost", 5432, "gres", "gres"), None);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(lookup(&path, "localhost", 5432, "gres", "gres"), None);
+    }
+}
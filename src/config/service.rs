@@ -0,0 +1,177 @@
+//! `pg_service.conf` lookups.
+//!
+//! A service file is an INI-style list of named sections, each holding
+//! `key=value` connection parameters:
+//!
+//! ```text
+//! # comment
+//! [reports]
+//! host=db.example.com
+//! dbname=reports
+//! ```
+
+use super::{defaults::home_dir, Config};
+use crate::{error::PgError, Result};
+use std::{fs, io, path::Path, path::PathBuf};
+
+/// Where the system-wide service file lives if `PGSYSCONFDIR` is not set.
+/// This matches the Debian and Ubuntu packages of libpq.
+const DEFAULT_SYSCONFDIR: &str = "/etc/postgresql-common";
+
+/// Apply the settings for `service`, without overriding anything already
+/// set.  The per-user service file is searched first, then the system-wide
+/// one.
+pub(super) fn apply(
+    config: &mut Config,
+    service: &str,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Result<()> {
+    let user_file = match env("PGSERVICEFILE") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => home_dir(env).map(|home| home.join(".pg_service.conf")),
+    };
+    let system_file = env("PGSYSCONFDIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_SYSCONFDIR))
+        .join("pg_service.conf");
+
+    for path in user_file.iter().chain(Some(&system_file)) {
+        if let Some(params) = read_service(path, service)? {
+            for (key, value) in params {
+                config.set_default(&key, &value)?;
+            }
+            return Ok(());
+        }
+    }
+    Err(PgError::Error(format!(
+        "Definition of service {:?} not found",
+        service
+    )))
+}
+
+/// The parameters of `service` in the file at `path`, or `None` if the file
+/// or the service does not exist.
+fn read_service(path: &Path, service: &str) -> Result<Option<Vec<(String, String)>>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    parse_service(&contents, service).map_err(|message| {
+        PgError::Error(format!(
+            "Syntax error in service file {:?}: {}",
+            path, message
+        ))
+    })
+}
+
+fn parse_service(
+    contents: &str,
+    service: &str,
+) -> std::result::Result<Option<Vec<(String, String)>>, String> {
+    let mut params = None;
+    let mut in_service = false;
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(section) = line.strip_prefix('[') {
+            let name = section
+                .strip_suffix(']')
+                .ok_or_else(|| format!("unterminated section header on line {}", number + 1))?;
+            if in_service {
+                // The service has ended; the rest of the file is irrelevant.
+                break;
+            }
+            in_service = name == service;
+            if in_service {
+                params = Some(vec![]);
+            }
+            continue;
+        }
+        if !in_service {
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("missing \"=\" on line {}", number + 1))?;
+        let key = key.trim();
+        if key == "service" {
+            return Err(format!("nested service on line {}", number + 1));
+        }
+        if let Some(params) = &mut params {
+            params.push((key.to_string(), value.trim().to_string()));
+        }
+    }
+    Ok(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::HashMap, env};
+
+    const SERVICES: &str = "
+# Shared services
+[reports]
+host=db.example.com
+port = 5433
+dbname=reports
+
+[broken]
+host
+";
+
+    #[test]
+    fn test_parse_service() {
+        assert_eq!(
+            parse_service(SERVICES, "reports").unwrap(),
+            Some(vec![
+                ("host".to_string(), "db.example.com".to_string()),
+                ("port".to_string(), "5433".to_string()),
+                ("dbname".to_string(), "reports".to_string()),
+            ])
+        );
+        assert_eq!(parse_service(SERVICES, "missing").unwrap(), None);
+        assert!(parse_service(SERVICES, "broken").is_err());
+        assert!(parse_service("[a]\nservice=b\n", "a").is_err());
+        assert!(parse_service("[a\n", "a").is_err());
+    }
+
+    #[test]
+    fn test_service_lookup() {
+        let dir = env::temp_dir().join(format!("gres-service-{}", std::process::id()));
+        fs::create_dir_all(dir.join("etc")).unwrap();
+        fs::write(dir.join("user.conf"), "[reports]\nuser=analyst\n").unwrap();
+        fs::write(dir.join("etc/pg_service.conf"), SERVICES).unwrap();
+        let vars: HashMap<&str, String> = vec![
+            ("PGSERVICEFILE", dir.join("user.conf").display().to_string()),
+            ("PGSYSCONFDIR", dir.join("etc").display().to_string()),
+            ("PGHOST", "elsewhere".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        let env = |name: &str| vars.get(name).cloned();
+
+        // The user's file has the service, so the system file is ignored.
+        let mut config = Config::new();
+        config.service("reports").dbname("mine");
+        let config = config.with_defaults(&env).unwrap();
+        assert_eq!(config.get_user(), Some("analyst"));
+        assert_eq!(config.get_dbname(), Some("mine"));
+        assert_eq!(config.get_hosts(), ["elsewhere"]);
+
+        // The service file takes precedence over the environment.
+        fs::remove_file(dir.join("user.conf")).unwrap();
+        let config: Config = "service=reports".parse().unwrap();
+        let config = config.with_defaults(&env).unwrap();
+        assert_eq!(config.get_hosts(), ["db.example.com"]);
+        assert_eq!(config.get_ports(), [5433]);
+        assert_eq!(config.get_dbname(), Some("reports"));
+
+        let config: Config = "service=nonexistent".parse().unwrap();
+        assert!(config.with_defaults(&env).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,25 +1,29 @@
 use crate::{
-    auth,
+    auth::{self, ChannelBinding},
+    config::{self, Config, TargetSessionAttrs},
     error::PgError,
+    row::{Column, Row},
+    tls::{self, Stream, TlsConfig},
+    transaction::{self, Transaction, TransactionOptions},
+    types::{self, Oid, ToSql},
     Result,
 };
 use gres_protocol::messages::{
     client::{
-        BindMessage, CloseMessage, CloseType, Message, ParseMessage, PasswordMessage, Query,
-        StartupMessage, Terminate,
-    },
-    server::{
-        take_msg, AuthMsg, NoticeBody, ServerMsg,
+        BindMessage, CloseMessage, CloseType, CopyDoneMessage, CopyFailMessage, DescribeMessage,
+        DescribeType, ExecuteMessage, Format, Message, ParseMessage, PasswordMessage, Query,
+        SaslInitialResponse, SaslResponse, StartupMessage, SyncMessage, Terminate,
     },
+    decoder::ServerMessageDecoder,
+    server::{AuthMsg, FieldFormat, NoticeBody, ServerMsg, TransactionStatus},
 };
 
 
 use std::{
-    cell::Cell,
-    collections::vec_deque::VecDeque,
-    io::{Read, Write},
-    net,
-    time::Duration,
+    cell::{Cell, RefCell},
+    io::{self, Write},
+    net::{self, ToSocketAddrs},
+    sync::Arc,
 };
 
 #[derive(Copy, Debug, Eq, PartialEq, Clone)]
@@ -41,17 +45,26 @@ pub struct Connection {
     password: Option<String>,
     host: String,
     port: u16,
-    socket: net::TcpStream,
+    socket: RefCell<Stream>,
+    decoder: RefCell<ServerMessageDecoder>,
+    scram: RefCell<Option<auth::ScramSha256>>,
+    channel_binding: ChannelBinding,
+    /// Channel binding data for the server certificate, if the connection
+    /// is encrypted.
+    tls_server_end_point: Option<Vec<u8>>,
+    /// Send cleartext passwords even if the connection is not encrypted.
+    allow_cleartext_password: bool,
     state: Cell<ConnectionState>,
+    transaction_status: Cell<TransactionStatus>,
     query_number: Cell<u32>,
 }
 
 impl Connection {
-    fn initiate_connection(&self) -> Result<()> {
+    fn initiate_connection(&self, params: Vec<(String, String)>) -> Result<()> {
         let startup = StartupMessage {
             user: &self.user,
             database: Some(&self.database),
-            params: vec![],
+            params,
         };
         self.send_message(&startup)?;
         self.state.set(ConnectionState::AwaitingAuthResponse);
@@ -59,84 +72,124 @@ impl Connection {
     }
 
     fn handle_startup(&self) -> Result<()> {
-        while match self.state.get() {
-            ConnectionState::ReadyForQuery => false,
-            ConnectionState::AuthenticationRejected => false,
-            _ => true,
-        } {
-            println!("AQUI");
-            let mut buf = Vec::with_capacity(1024);
-            let mut message_queue = VecDeque::new();
-            self.read_from_socket(&mut buf)?;
-            let mut remainder = &buf[..];
-            while !remainder.is_empty() {
-                let (bytes, excess) = take_msg(remainder)?;
-                let msg = ServerMsg::from_slice(bytes)?;
-                message_queue.push_back(msg);
-                remainder = excess;
-            }
-            println!("VecDeque: {:?}", message_queue);
-            while !message_queue.is_empty() {
-                match self.state.get() {
-                    ConnectionState::AwaitingAuthResponse => {
-                        self.handle_auth(&mut message_queue)?
-                    }
-                    ConnectionState::AuthenticationRejected => false,
-                    ConnectionState::Authenticated => {
-                        self.handle_server_info(&mut message_queue)?
-                    }
-                    ConnectionState::ReadyForQuery => {
-                        self.handle_ready_for_query(&mut message_queue)?;
-                        break;
-                    }
-                    state => {
-                        return Err(PgError::Error(format!(
-                            "Invalid startup state: {:?}",
-                            state
-                        )))
-                    }
-                };
+        loop {
+            match self.state.get() {
+                ConnectionState::ReadyForQuery | ConnectionState::AuthenticationRejected => {
+                    return Ok(())
+                }
+                ConnectionState::AwaitingAuthResponse => {
+                    self.read_message(|msg| self.handle_auth(msg))?
+                }
+                ConnectionState::Authenticated => {
+                    self.read_message(|msg| self.handle_server_info(msg))?
+                }
+                state => {
+                    return Err(PgError::Error(format!(
+                        "Invalid startup state: {:?}",
+                        state
+                    )))
+                }
             }
         }
-        Ok(())
     }
 
-    fn handle_auth<'a>(&self, message_queue: &mut VecDeque<ServerMsg<'a>>) -> Result<bool> {
-        let msg = message_queue.pop_front();
+    fn handle_auth(&self, msg: ServerMsg<'_>) -> Result<()> {
         match msg {
-            Some(ServerMsg::Auth(AuthMsg::Ok)) => {
+            ServerMsg::Auth(AuthMsg::Ok) => {
+                let scram = self.scram.borrow();
+                if let Some(scram) = &*scram {
+                    if !scram.is_complete() {
+                        return Err(PgError::Error(
+                            "Server accepted SCRAM authentication without proving it knows the password"
+                                .to_string(),
+                        ));
+                    }
+                }
+                let channel_bound = scram.as_ref().is_some_and(|s| s.uses_channel_binding());
+                if self.channel_binding == ChannelBinding::Require && !channel_bound {
+                    return Err(PgError::Error(
+                        "channel_binding=require, but the server authenticated without channel binding"
+                            .to_string(),
+                    ));
+                }
                 self.state.set(ConnectionState::Authenticated);
-                Ok(false)
+                Ok(())
             }
-            Some(ServerMsg::Auth(AuthMsg::Md5(salt))) => {
+            ServerMsg::Auth(method @ AuthMsg::Md5(_))
+            | ServerMsg::Auth(method @ AuthMsg::Cleartext)
+                if self.channel_binding == ChannelBinding::Require =>
+            {
+                Err(PgError::Error(format!(
+                    "channel_binding=require, but the server requested {:?}",
+                    method
+                )))
+            }
+            ServerMsg::Auth(AuthMsg::Md5(salt)) => {
                 let password = &self.password.clone().unwrap_or_default();
                 let passhash = auth::build_md5_hash(&self.user, password, salt);
                 let password_message = PasswordMessage { hash: &passhash };
-                self.send_message(&password_message)?;
-                Ok(true)
+                self.send_message(&password_message)
+            }
+            ServerMsg::Auth(AuthMsg::Cleartext) => {
+                let local = self.socket.borrow().is_local();
+                if !self.is_encrypted() && !local && !self.allow_cleartext_password {
+                    return Err(PgError::Error(
+                        "Server requested a cleartext password over an unencrypted connection"
+                            .to_string(),
+                    ));
+                }
+                let password = self.password.clone().unwrap_or_default();
+                self.send_message(&PasswordMessage { hash: &password })
+            }
+            ServerMsg::Auth(AuthMsg::Sasl(mechanisms)) => {
+                let (mechanism, binding) = auth::select_scram_mechanism(
+                    &mechanisms,
+                    self.channel_binding,
+                    self.tls_server_end_point.as_deref(),
+                )?;
+                let password = self.password.clone().unwrap_or_default();
+                let scram = auth::ScramSha256::new(&password, binding);
+                let client_first = scram.client_first_message();
+                *self.scram.borrow_mut() = Some(scram);
+                self.send_message(&SaslInitialResponse {
+                    mechanism,
+                    data: Some(&client_first),
+                })
+            }
+            ServerMsg::Auth(AuthMsg::SaslContinue(data)) => {
+                let client_final = match &mut *self.scram.borrow_mut() {
+                    Some(scram) => scram.handle_server_first(data)?,
+                    None => return Err(PgError::Error("Unexpected SASLContinue".to_string())),
+                };
+                self.send_message(&SaslResponse {
+                    data: &client_final,
+                })
             }
-            Some(ServerMsg::Auth(method)) => Err(PgError::Error(format!(
+            ServerMsg::Auth(AuthMsg::SaslFinal(data)) => match &mut *self.scram.borrow_mut() {
+                Some(scram) => scram.verify_server_final(data),
+                None => Err(PgError::Error("Unexpected SASLFinal".to_string())),
+            },
+            ServerMsg::Auth(method) => Err(PgError::Error(format!(
                 "Unimplemented authentication method, {:?}",
                 method
             ))),
-            Some(ServerMsg::ErrorResponse(err)) => self.handle_auth_error(err)?,
-            Some(msg) => Err(PgError::Error(format!(
+            ServerMsg::ErrorResponse(err) => self.handle_auth_error(err),
+            msg => Err(PgError::Error(format!(
                 "Unexpected non-auth message: {:?}",
                 msg
             ))),
-            None => Err(PgError::Error("No message received".to_string())),
         }
     }
 
-    fn handle_server_info<'a>(&self, message_queue: &mut VecDeque<ServerMsg<'a>>) -> Result<bool> {
-        match message_queue.pop_front() {
-            Some(ServerMsg::ReadyForQuery) => {
+    fn handle_server_info(&self, msg: ServerMsg<'_>) -> Result<()> {
+        match msg {
+            ServerMsg::ReadyForQuery(status) => {
+                self.transaction_status.set(status);
                 self.state.set(ConnectionState::ReadyForQuery);
-                Ok(false)
+                Ok(())
             }
-            Some(ServerMsg::ErrorResponse(err)) => self.handle_error(err)?,
-            Some(_) => Ok(false),
-            None => Ok(true),
+            ServerMsg::ErrorResponse(err) => self.handle_error(err),
+            _ => Ok(()),
         }
     }
 
@@ -144,54 +197,102 @@ impl Connection {
         self.state.set(ConnectionState::AuthenticationRejected);
         self.handle_error(err)
     }
+
     fn handle_error<T>(&self, err: NoticeBody<'_>) -> Result<T> {
-        Err(PgError::Error(err.message().to_string()))
+        Err(err.into())
     }
 
-    fn handle_ready_for_query<'a>(
-        &self,
-        message_queue: &mut VecDeque<ServerMsg<'a>>,
-    ) -> Result<bool> {
-        match message_queue.pop_front() {
-            Some(msg) => Err(PgError::Error(format!(
-                "Unexpected message after ReadyForQuery: {:?}",
-                msg
-            ))),
-            None => Ok(false),
-        }
+    pub fn new(
+        user: &str,
+        password: Option<&str>,
+        host: &str,
+        database: Option<&str>,
+    ) -> Result<Connection> {
+        Connection::positional_config(user, password, host, database).connect()
     }
 
-    pub fn new(
+    pub fn new_with_tls(
         user: &str,
         password: Option<&str>,
         host: &str,
         database: Option<&str>,
+        tls: &TlsConfig,
     ) -> Result<Connection> {
-        let database = match database {
-            Some(db) => db.to_string(),
-            None => user.to_string(),
-        };
-        let password = match password {
-            Some(pass) => Some(pass.to_string()),
-            None => None,
+        Connection::positional_config(user, password, host, database)
+            .tls(tls.clone())
+            .connect()
+    }
+
+    fn positional_config(
+        user: &str,
+        password: Option<&str>,
+        host: &str,
+        database: Option<&str>,
+    ) -> Config {
+        let mut config = Config::new();
+        config.user(user).host(host);
+        if let Some(password) = password {
+            config.password(password);
+        }
+        if let Some(database) = database {
+            config.dbname(database);
+        }
+        config
+    }
+
+    /// Connect to each host in turn, returning the first connection that
+    /// succeeds and matches the `target_session_attrs`.
+    pub(crate) fn connect(config: &Config) -> Result<Connection> {
+        let tls = config.load_tls()?;
+        let mut last_error = None;
+        for (host, port) in config.addresses()? {
+            match Connection::connect_host(config, &tls, host, port) {
+                Ok(conn) => {
+                    match conn.check_target_session_attrs(config.get_target_session_attrs()) {
+                        Ok(()) => return Ok(conn),
+                        Err(err) => last_error = Some(err),
+                    }
+                }
+                Err(err) => last_error = Some(err),
+            }
+        }
+        Err(last_error.expect("config has at least one address"))
+    }
+
+    fn connect_host(config: &Config, tls: &TlsConfig, host: &str, port: u16) -> Result<Connection> {
+        let user = config
+            .get_user()
+            .ok_or_else(|| PgError::Error("No user specified".to_string()))?
+            .to_string();
+        let database = config.get_dbname().unwrap_or(&user).to_string();
+        let password = config.password_for(host, port, &database, &user);
+        let socket = if config::is_socket_dir(host) {
+            // As in libpq, TLS is not used over Unix domain sockets.
+            let socket = connect_unix(host, port)?;
+            socket.set_read_timeout(config.get_read_timeout())?;
+            socket.set_write_timeout(config.get_write_timeout())?;
+            socket
+        } else {
+            tls::negotiate(|| connect_tcp(host, port, config), host, tls)?
         };
-        let user = user.to_string();
-        let host = host.to_string();
-        let port = 5432;
-        let socket = net::TcpStream::connect((host.as_str(), port))?;
-        socket.set_read_timeout(Some(Duration::new(0, 1)))?;
-        socket.set_nodelay(true)?;
+        let tls_server_end_point = socket.peer_certificate().map(auth::tls_server_end_point);
         let conn = Connection {
             user,
             password,
             database,
-            host,
+            host: host.to_string(),
             port,
-            socket,
+            socket: RefCell::new(socket),
+            decoder: RefCell::new(ServerMessageDecoder::new()),
+            scram: RefCell::new(None),
+            channel_binding: config.get_channel_binding(),
+            tls_server_end_point,
+            allow_cleartext_password: config.get_allow_cleartext_password(),
             state: Cell::new(ConnectionState::New),
+            transaction_status: Cell::new(TransactionStatus::Idle),
             query_number: Cell::new(0),
         };
-        conn.initiate_connection()?;
+        conn.initiate_connection(config.startup_params())?;
         conn.handle_startup()?;
         match conn.state.get() {
             ConnectionState::ReadyForQuery => Ok(conn),
@@ -200,110 +301,250 @@ impl Connection {
         }
     }
 
-    fn send_message<M: Message + std::fmt::Debug>(&self, message: &M) -> Result<()> {
-        let buf = dbg!(message).to_bytes();
-        dbg!(dbg!(&buf).len());
-        ::log::trace!("sending {:?}", buf);
-        (&self.socket).write_all(&buf)?;
+    fn check_target_session_attrs(&self, attrs: TargetSessionAttrs) -> Result<()> {
+        let (sql, expected) = match attrs {
+            TargetSessionAttrs::Any => return Ok(()),
+            TargetSessionAttrs::ReadWrite => ("SHOW transaction_read_only;", "off"),
+            TargetSessionAttrs::ReadOnly => ("SHOW transaction_read_only;", "on"),
+            TargetSessionAttrs::Primary => ("SELECT pg_is_in_recovery();", "f"),
+            TargetSessionAttrs::Standby => ("SELECT pg_is_in_recovery();", "t"),
+        };
+        let rows = self.simple_query(sql)?;
+        match rows.first().and_then(|row| row.first()) {
+            Some(Some(value)) if value == expected => Ok(()),
+            _ => Err(PgError::Error(format!(
+                "Server at {}:{} does not match target_session_attrs {:?}",
+                self.host, self.port, attrs
+            ))),
+        }
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.socket.borrow().is_encrypted()
+    }
+
+    /// The transaction status reported by the server at the end of the
+    /// last query.
+    pub fn transaction_status(&self) -> TransactionStatus {
+        self.transaction_status.get()
+    }
+
+    /// Start a transaction, which rolls back when the returned guard is
+    /// dropped unless it is committed.
+    pub fn transaction(&self) -> Result<Transaction<'_>> {
+        self.transaction_with(&TransactionOptions::default())
+    }
+
+    /// Start a transaction with the given isolation level and access mode.
+    pub fn transaction_with(&self, options: &TransactionOptions) -> Result<Transaction<'_>> {
+        Transaction::begin(self, options)
+    }
+
+    /// Run `f` in a transaction and commit it.  If the transaction fails
+    /// with a serialization failure or deadlock, it is rolled back and `f`
+    /// is run again, up to the attempts allowed by `options`.
+    ///
+    /// `f` may run several times, so it should not have side effects
+    /// outside the database.
+    pub fn run_in_transaction<T, F>(&self, options: &TransactionOptions, f: F) -> Result<T>
+    where
+        F: FnMut(&mut Transaction<'_>) -> Result<T>,
+    {
+        transaction::run(self, options, f)
+    }
+
+    fn send_message<M: Message>(&self, message: &M) -> Result<()> {
+        let buf = message.to_bytes();
+        // Only the type and length: the bytes may hold a password.
+        ::log::trace!(
+            "sending message {:?}, {} bytes",
+            message.id().map(char::from),
+            buf.len()
+        );
+        self.socket.borrow_mut().write_all(&buf)?;
         Ok(())
     }
 
-    // This looks wrong?
-    fn read_from_socket(&self, buf: &mut Vec<u8>) -> Result<usize> {
-        while buf.is_empty() {
-            match (&self.socket).read_to_end(buf) {
-                Ok(_) => continue,
-                Err(ioerr) => {
-                    if let Some(11) = ioerr.raw_os_error() {
-                        continue;
-                    } else {
-                        return Err(ioerr.into());
-                    }
-                }
+    /// Read from the socket until a complete message is available, and pass
+    /// it to `handler`.  Any excess bytes stay buffered for the next call.
+    fn read_message<T, F>(&self, handler: F) -> Result<T>
+    where
+        F: FnOnce(ServerMsg<'_>) -> Result<T>,
+    {
+        let mut decoder = self.decoder.borrow_mut();
+        while !decoder.has_frame()? {
+            let read = decoder.read_from(&mut *self.socket.borrow_mut())?;
+            if read == 0 {
+                self.state.set(ConnectionState::Disconnected);
+                return Err(PgError::Io(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "The server closed the connection",
+                )));
             }
         }
-        ::log::trace!("received {:?}", buf);
-        Ok(buf.len())
+        let msg = decoder
+            .decode()?
+            .expect("decoder reported a complete frame");
+        handler(msg)
     }
 
-    pub fn simple_query(&self, sql: &str) -> Result<Vec<Vec<String>>> {
+    pub fn simple_query(&self, sql: &str) -> Result<Vec<Vec<Option<String>>>> {
+        self.simple_query_iter(sql)?
+            .map(|row| {
+                let row = row?;
+                (0..row.len()).map(|i| row.try_get(i)).collect()
+            })
+            .collect()
+    }
+
+    /// Run `sql` with the simple query protocol, reading the rows from the
+    /// socket as they arrive.  All values are in text format.
+    pub fn simple_query_iter(&self, sql: &str) -> Result<RowIter<'_>> {
+        self.start_query()?;
         let query = Query {
             query: sql.to_string(),
         };
         self.send_message(&query)?;
-        self.state.set(ConnectionState::AwaitingQueryResponse);
-        let mut buf: Vec<u8> = vec![];
-        self.read_from_socket(&mut buf)?;
-        let mut remainder = &buf[..];
-        let mut data = vec![];
-
-        while !remainder.is_empty() {
-            let (bytes, excess) = take_msg(remainder)?;
-            let msg = ServerMsg::from_slice(bytes)?;
-            remainder = excess;
-            match msg {
-                ServerMsg::DataRow(vec) => {
-                    let mut row = vec![];
-                    for each in vec {
-                        row.push(each.to_string());
-                    }
-                    data.push(row);
-                }
-                ServerMsg::RowDescription(_) => {
-                    self.state.set(ConnectionState::AwaitingDataRows);
-                }
-                ServerMsg::CommandComplete(_) => {}
-                ServerMsg::ReadyForQuery => {
-                    if !remainder.is_empty() {
-                        return Err(PgError::Error(format!(
-                            "Received data after ReadyForQuery: {:?}",
-                            remainder
-                        )));
-                    };
-                    self.state.set(ConnectionState::ReadyForQuery);
-                }
-                ServerMsg::NoticeResponse(r) => ::log::info!("{:?}", r),
-                other => return Err(PgError::Error(format!("unexpected data: {:?}", other))),
+        Ok(RowIter::new(self, Arc::from(vec![])))
+    }
+
+    /// Check that the results of the last query have been read, before
+    /// sending a new one.
+    fn start_query(&self) -> Result<()> {
+        match self.state.get() {
+            ConnectionState::ReadyForQuery => {
+                self.state.set(ConnectionState::AwaitingQueryResponse);
+                Ok(())
             }
+            ConnectionState::AwaitingQueryResponse | ConnectionState::AwaitingDataRows => Err(
+                PgError::Error("The results of the previous query have not been read".to_string()),
+            ),
+            ConnectionState::Disconnected => Err(PgError::Error(
+                "The connection was closed, or fell out of step with the server".to_string(),
+            )),
+            state => Err(PgError::Error(format!(
+                "Cannot start a query in state {:?}",
+                state
+            ))),
         }
-        Ok(data)
     }
-    pub fn query<'a>(
-        &'a self,
-        sql: &str,
-        _params: impl IntoIterator<Item = Param<'a>>,
-    ) -> Result<Vec<Vec<String>>> {
-        let _qh = self.prepare(sql)?;
-        todo!()
+
+    /// Run `sql` with the extended query protocol, passing `params` to the
+    /// server separately from the query text, so they never need to be
+    /// escaped.
+    pub fn query(&self, sql: &str, params: &[&dyn ToSql]) -> Result<Vec<Row>> {
+        self.query_iter(sql, params)?.collect()
     }
 
+    /// Like `query`, but read the rows from the socket as they arrive.
+    pub fn query_iter(&self, sql: &str, params: &[&dyn ToSql]) -> Result<RowIter<'_>> {
+        let statement = self.parse("", sql)?;
+        self.execute(&statement, params)
+    }
+
+    /// Parse `sql` into a named prepared statement, which can then be
+    /// executed any number of times with different parameters.  The
+    /// statement is closed when the handle is dropped.
     pub fn prepare<'a>(&'a self, sql: &str) -> Result<QueryHandle<'a>> {
         let query_number = self.query_number.get();
-        let query_name = query_number.to_string();
         self.query_number.set(query_number + 1);
+        Ok(QueryHandle {
+            statement: self.parse(&query_number.to_string(), sql)?,
+            conn: self,
+        })
+    }
+
+    /// Parse `sql` into the statement `name`, and describe its parameters
+    /// and result columns.  The empty name is the unnamed statement, which
+    /// lasts until the next one is parsed.
+    fn parse(&self, name: &str, sql: &str) -> Result<Statement> {
+        self.start_query()?;
         let parse_message = ParseMessage {
-            name: &query_name,
+            name,
             sql,
             param_types: &[],
         };
         self.send_message(&parse_message)?;
-        let mut buf: Vec<u8> = vec![];
-        self.read_from_socket(&mut buf)?;
-        let (msg, rest) = take_msg(&buf)?;
-        if rest.is_empty() {
-            match ServerMsg::from_slice(msg)? {
-                ServerMsg::ParseComplete => Ok(QueryHandle {
-                    query_name,
-                    conn: self,
-                }),
-                ServerMsg::ErrorResponse(err) => {
-                    Err(PgError::Error(format!("ServerError: {:?}", err)))
+        self.send_message(&DescribeMessage {
+            describe_type: DescribeType::PreparedStatement,
+            name,
+        })?;
+        self.send_message(&SyncMessage)?;
+
+        let mut param_types = vec![];
+        let mut columns = vec![];
+        let mut error = None;
+        loop {
+            let done = self.read_message(|msg| {
+                match msg {
+                    ServerMsg::ParseComplete | ServerMsg::NoData => {}
+                    ServerMsg::ParameterDescription(types) => param_types = types,
+                    ServerMsg::RowDescription(fields) => {
+                        columns = fields.iter().map(Column::new).collect()
+                    }
+                    ServerMsg::ReadyForQuery(status) => {
+                        self.transaction_status.set(status);
+                        self.state.set(ConnectionState::ReadyForQuery);
+                        return Ok(true);
+                    }
+                    ServerMsg::NoticeResponse(r) => ::log::info!("{:?}", r),
+                    ServerMsg::ErrorResponse(err) => error = self.handle_error::<()>(err).err(),
+                    msg => return Err(PgError::Error(format!("Unexpected message: {:?}", msg))),
                 }
-                msg => Err(PgError::Error(format!("Unexpected message: {:?}", msg))),
+                Ok(false)
+            })?;
+            if done {
+                break;
             }
-        } else {
-            Err(PgError::Other)
         }
+        if let Some(err) = error {
+            return Err(err);
+        }
+        Ok(Statement {
+            name: name.to_string(),
+            param_types,
+            columns: columns.into_iter().map(result_column).collect(),
+        })
+    }
+
+    /// Bind `params` to `statement` in the unnamed portal and run it.
+    fn execute(&self, statement: &Statement, params: &[&dyn ToSql]) -> Result<RowIter<'_>> {
+        let (formats, values) = statement.encode_params(params)?;
+        let result_formats = statement.result_formats();
+        self.start_query()?;
+        self.send_message(&statement.bind_message("", &formats, &values, &result_formats))?;
+        self.send_message(&ExecuteMessage {
+            portal: "",
+            max_rows: 0,
+        })?;
+        self.send_message(&SyncMessage)?;
+        Ok(RowIter::new(self, statement.columns.clone()))
+    }
+
+    /// Send Sync, and read the responses to the messages before it, which
+    /// must not return rows.
+    fn sync(&self) -> Result<()> {
+        self.send_message(&SyncMessage)?;
+        match RowIter::new(self, Arc::from(vec![])).next() {
+            None => Ok(()),
+            Some(Err(err)) => Err(err),
+            Some(Ok(row)) => Err(PgError::Error(format!("Unexpected row: {:?}", row))),
+        }
+    }
+
+    /// Close a prepared statement or portal.
+    fn close(&self, close_type: CloseType, name: &str) -> Result<()> {
+        self.start_query()?;
+        self.send_message(&CloseMessage { close_type, name })?;
+        self.sync()
     }
 }
 
@@ -323,129 +564,451 @@ impl Drop for Connection {
     }
 }
 
-pub enum Param<'a> {
-    String(&'a str),
-    Binary(&'a [u8]),
-    Integer(i64),
-    Boolean(bool),
-    Null,
+/// Open a TCP connection to the first address `host` resolves to that
+/// accepts it, applying the timeout and keepalive settings.
+fn connect_tcp(host: &str, port: u16, config: &Config) -> Result<net::TcpStream> {
+    let mut last_error = None;
+    for addr in (host, port).to_socket_addrs()? {
+        let result = match config.get_connect_timeout() {
+            Some(timeout) => net::TcpStream::connect_timeout(&addr, timeout),
+            None => net::TcpStream::connect(addr),
+        };
+        match result {
+            Ok(socket) => {
+                socket.set_nodelay(true)?;
+                // Set before TLS negotiation, so a server that never answers
+                // the SSLRequest or the handshake can't hang the connect.
+                socket.set_read_timeout(config.get_read_timeout())?;
+                socket.set_write_timeout(config.get_write_timeout())?;
+                let sockref = socket2::SockRef::from(&socket);
+                sockref.set_keepalive(config.get_keepalives())?;
+                if config.get_keepalives() {
+                    sockref.set_tcp_keepalive(&keepalive_params(config))?;
+                }
+                return Ok(socket);
+            }
+            Err(err) => last_error = Some(err),
+        }
+    }
+    Err(last_error
+        .unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Could not resolve host {:?}", host),
+            )
+        })
+        .into())
+}
+
+/// Connect to the Unix domain socket for `port` in the directory `dir`.
+#[cfg(unix)]
+fn connect_unix(dir: &str, port: u16) -> Result<Stream> {
+    let path = std::path::Path::new(dir).join(format!(".s.PGSQL.{}", port));
+    Ok(Stream::Unix(std::os::unix::net::UnixStream::connect(path)?))
+}
+
+#[cfg(not(unix))]
+fn connect_unix(dir: &str, _port: u16) -> Result<Stream> {
+    Err(PgError::Error(format!(
+        "Cannot connect to socket directory {:?}: Unix domain sockets are not supported on this platform",
+        dir
+    )))
 }
 
+fn keepalive_params(config: &Config) -> socket2::TcpKeepalive {
+    let mut keepalive = socket2::TcpKeepalive::new();
+    if let Some(idle) = config.get_keepalives_idle() {
+        keepalive = keepalive.with_time(idle);
+    }
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "freebsd",
+        target_os = "netbsd"
+    ))]
+    {
+        if let Some(interval) = config.get_keepalives_interval() {
+            keepalive = keepalive.with_interval(interval);
+        }
+        if let Some(count) = config.get_keepalives_count() {
+            keepalive = keepalive.with_retries(count);
+        }
+    }
+    keepalive
+}
+
+/// Fetch columns of types we can decode in binary format that way, and the
+/// rest as text.
+fn result_column(column: Column) -> Column {
+    let format = if types::has_binary_format(column.type_oid()) {
+        FieldFormat::Binary
+    } else {
+        FieldFormat::Text
+    };
+    column.with_format(format)
+}
+
+/// Parameter formats and values, ready for a Bind message.
+type EncodedParams = (Vec<Format>, Vec<Option<Vec<u8>>>);
+
+/// A statement parsed by the server.
+struct Statement {
+    name: String,
+    param_types: Vec<Oid>,
+    /// The result columns, with the formats they are fetched in.
+    columns: Arc<[Column]>,
+}
+
+impl Statement {
+    fn bind_message<'a>(
+        &'a self,
+        portal: &'a str,
+        formats: &'a [Format],
+        values: &'a [Option<Vec<u8>>],
+        result_formats: &'a [Format],
+    ) -> BindMessage<'a> {
+        BindMessage {
+            portal,
+            prepared_statement: &self.name,
+            param_format_codes: formats,
+            param_values: values,
+            result_format_codes: result_formats,
+        }
+    }
+
+    fn result_formats(&self) -> Vec<Format> {
+        self.columns
+            .iter()
+            .map(|column| match column.format() {
+                FieldFormat::Text => Format::Text,
+                FieldFormat::Binary => Format::Binary,
+            })
+            .collect()
+    }
+
+    fn encode_params(&self, params: &[&dyn ToSql]) -> Result<EncodedParams> {
+        if params.len() != self.param_types.len() {
+            return Err(PgError::Error(format!(
+                "Statement expects {} parameters, but {} were given",
+                self.param_types.len(),
+                params.len()
+            )));
+        }
+        params
+            .iter()
+            .zip(&self.param_types)
+            .map(|(param, &ty)| param.to_sql(ty))
+            .collect::<Result<Vec<_>>>()
+            .map(|encoded| encoded.into_iter().unzip())
+    }
+}
+
+/// A prepared statement on the server.
 pub struct QueryHandle<'conn> {
     conn: &'conn Connection,
-    query_name: String,
+    statement: Statement,
 }
 
 impl<'conn> QueryHandle<'conn> {
+    /// The type OIDs of the statement's parameters.
+    pub fn param_types(&self) -> &[Oid] {
+        &self.statement.param_types
+    }
+
+    /// The columns the statement returns.
+    pub fn columns(&self) -> &[Column] {
+        &self.statement.columns
+    }
+
+    /// Execute the statement with `params`.
+    pub fn query(&self, params: &[&dyn ToSql]) -> Result<Vec<Row>> {
+        self.query_iter(params)?.collect()
+    }
+
+    /// Like `query`, but read the rows from the socket as they arrive.
+    pub fn query_iter<'a>(&'a self, params: &[&dyn ToSql]) -> Result<RowIter<'a>> {
+        self.conn.execute(&self.statement, params)
+    }
+
+    /// Bind `params` to the statement in the portal `portal_name`, whose
+    /// rows can then be fetched a few at a time.
+    ///
+    /// Portals last until the end of the transaction, so this must be
+    /// called inside one.
     pub fn bind<'a>(
         &'a self,
         portal_name: String,
-        params: &[Vec<u8>],
+        params: &[&dyn ToSql],
     ) -> Result<Portal<'a, 'conn>> {
-        let msg = BindMessage {
-            portal: &portal_name,
-            prepared_statement: &self.query_name,
-            param_format_codes: &[],
-            param_values: params,
-            result_format_codes: &[],
-        };
-        self.conn.send_message(&msg)?;
-
-        // TODO: Query the portal parameters
-
+        if self.conn.transaction_status() != TransactionStatus::InTransaction {
+            return Err(PgError::Error(
+                "Portals can only be bound inside a transaction".to_string(),
+            ));
+        }
+        let (formats, values) = self.statement.encode_params(params)?;
+        let result_formats = self.statement.result_formats();
+        self.conn.start_query()?;
+        self.conn.send_message(&self.statement.bind_message(
+            &portal_name,
+            &formats,
+            &values,
+            &result_formats,
+        ))?;
+        self.conn.sync()?;
         Ok(Portal {
             query_handle: self,
             portal_name,
-            row_format: None,
+            closed: false,
         })
     }
 }
 
 impl<'conn> Drop for QueryHandle<'conn> {
     fn drop(&mut self) {
-        let msg = CloseMessage {
-            close_type: CloseType::PreparedStatement,
-            name: &self.query_name,
-        };
-        let _ = self.conn.send_message(&msg);
-        let mut reply = Vec::new();
-        match self.conn.read_from_socket(&mut reply) {
-            Err(err) => ::log::error!("Error: {}", err),
-            Ok(_) => {
-                let mut remainder: &[u8] = &reply;
-                while !remainder.is_empty() {
-                    let msg = take_msg(&remainder)
-                        .map(|(body, extra)|{
-                            remainder = extra;
-                            body
-                        })
-                        .and_then(ServerMsg::from_slice);
-                    match msg {
-                        Ok(ServerMsg::CloseComplete) => {}
-                        Ok(ServerMsg::ErrorResponse(notice_body)) => {
-                            eprintln!(
-                                "Error while dropping queryhandle for {:?}: {:?}",
-                                self.query_name,
-                                notice_body,
-                            );
-
-                            eprintln!("Error ")
-                        }
-                        Ok(ServerMsg::NoticeResponse(notice_body)) => {
-                            ::log::info!("Notice: {:?}", notice_body);
-                        }
-                        Ok(msg) => {
-                            eprintln!(
-                                "Unexpected response while dropping queryhandle for {:?}: {:?}",
-                                self.query_name,
-                                msg,
-                            )
-                        }
-                        Err(err) => {
-                            eprintln!(
-                                "Error receiving response while dropping queryhandle for {:?}: {}",
-                                self.query_name, err
-                            );
-                            break;
-                        }
-                    }
-                }
-            }
+        let closed = self
+            .conn
+            .close(CloseType::PreparedStatement, &self.statement.name);
+        if let Err(err) = closed {
+            ::log::warn!(
+                "Error closing queryhandle for {:?}: {}",
+                self.statement.name,
+                err
+            );
         }
-        todo!()
     }
 }
 
-struct RowFormat {
+/// How a fetch from a portal ended.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PortalStatus {
+    /// The row limit was reached, and there may be more rows to fetch.
+    Suspended,
+    /// The query ran to completion, with this command tag.
+    Complete(String),
+}
+
+/// The rows from one `Portal::fetch`.
+#[derive(Debug)]
+pub struct Fetch {
+    pub rows: Vec<Row>,
+    pub status: PortalStatus,
+}
 
+impl Fetch {
+    /// Whether the portal has no more rows.
+    pub fn is_complete(&self) -> bool {
+        self.status != PortalStatus::Suspended
+    }
 }
 
+/// A statement bound to its parameters, which can return its rows over
+/// several fetches.  The portal is closed when it is dropped.
 pub struct Portal<'qh, 'conn> {
     query_handle: &'qh QueryHandle<'conn>,
     portal_name: String,
-    row_format: Option<RowFormat>,
+    closed: bool,
+}
+
+impl<'qh, 'conn> Portal<'qh, 'conn> {
+    pub fn name(&self) -> &str {
+        &self.portal_name
+    }
+
+    /// The columns the portal returns.
+    pub fn columns(&self) -> &[Column] {
+        self.query_handle.columns()
+    }
+
+    /// Fetch up to `max_rows` more rows from the portal.  Zero fetches all
+    /// the remaining rows.
+    pub fn fetch(&self, max_rows: u32) -> Result<Fetch> {
+        let conn = self.query_handle.conn;
+        conn.start_query()?;
+        conn.send_message(&ExecuteMessage {
+            portal: &self.portal_name,
+            max_rows,
+        })?;
+        conn.send_message(&SyncMessage)?;
+        let mut iter = RowIter::new(conn, self.query_handle.statement.columns.clone());
+        let rows = iter.by_ref().collect::<Result<Vec<_>>>()?;
+        match iter.status.take() {
+            Some(status) => Ok(Fetch { rows, status }),
+            None => Err(PgError::Error(
+                "Execute finished without completing or suspending the portal".to_string(),
+            )),
+        }
+    }
+
+    /// Close the portal, freeing its resources on the server.
+    pub fn close(mut self) -> Result<()> {
+        self.closed = true;
+        self.query_handle
+            .conn
+            .close(CloseType::Portal, &self.portal_name)
+    }
+}
+
+impl<'qh, 'conn> Drop for Portal<'qh, 'conn> {
+    fn drop(&mut self) {
+        if self.closed {
+            return;
+        }
+        if let Err(err) = self
+            .query_handle
+            .conn
+            .close(CloseType::Portal, &self.portal_name)
+        {
+            ::log::warn!("Error closing portal {:?}: {}", self.portal_name, err);
+        }
+    }
+}
+
+/// The rows of a query, decoded from the socket as they arrive.
+///
+/// The results must be read up to the end before the connection can run
+/// another query, so dropping the iterator early reads and discards the
+/// remaining rows.
+pub struct RowIter<'conn> {
+    conn: &'conn Connection,
+    columns: Arc<[Column]>,
+    /// An error from the server, returned once the rest of its response
+    /// has been read.
+    error: Option<PgError>,
+    /// How the last Execute ended.
+    status: Option<PortalStatus>,
+    done: bool,
+}
+
+impl<'conn> RowIter<'conn> {
+    fn new(conn: &'conn Connection, columns: Arc<[Column]>) -> RowIter<'conn> {
+        RowIter {
+            conn,
+            columns,
+            error: None,
+            status: None,
+            done: false,
+        }
+    }
+
+    /// Handle one message, returning the row if it was a data row.
+    fn handle(&mut self, msg: ServerMsg<'_>) -> Result<Option<Result<Row>>> {
+        match msg {
+            ServerMsg::DataRow(row) => return Ok(Some(Row::new(self.columns.clone(), &row))),
+            ServerMsg::RowDescription(fields) => {
+                self.columns = fields.iter().map(Column::new).collect();
+                self.conn.state.set(ConnectionState::AwaitingDataRows);
+            }
+            ServerMsg::BindComplete => self.conn.state.set(ConnectionState::AwaitingDataRows),
+            ServerMsg::CloseComplete => {}
+            ServerMsg::CommandComplete(tag) => {
+                self.status = Some(PortalStatus::Complete(tag.to_string()))
+            }
+            ServerMsg::EmptyQueryResponse => {
+                self.status = Some(PortalStatus::Complete(String::new()))
+            }
+            ServerMsg::PortalSuspended => self.status = Some(PortalStatus::Suspended),
+            ServerMsg::ReadyForQuery(status) => {
+                self.conn.transaction_status.set(status);
+                self.conn.state.set(ConnectionState::ReadyForQuery);
+                self.done = true;
+            }
+            ServerMsg::NoticeResponse(r) => ::log::info!("{:?}", r),
+            ServerMsg::NotificationResponse(pid, channel, payload) => {
+                ::log::info!("Notification from {} on {}: {}", pid, channel, payload)
+            }
+            // The server skips the rest of the query, but still finishes
+            // with ReadyForQuery.
+            ServerMsg::ErrorResponse(err) => self.error = self.conn.handle_error::<()>(err).err(),
+            // COPY to or from the client is not supported, so end it early.
+            // The server then finishes the query as usual.
+            ServerMsg::CopyInResponse(_) => {
+                let fail = CopyFailMessage {
+                    message: "COPY FROM STDIN is not supported",
+                };
+                self.conn.send_message(&fail)?;
+            }
+            ServerMsg::CopyOutResponse(_) => self.copy_not_supported(),
+            ServerMsg::CopyBothResponse(_) => {
+                // The server keeps streaming until the client ends its side.
+                self.conn.send_message(&CopyDoneMessage)?;
+                self.copy_not_supported();
+            }
+            ServerMsg::CopyData(_) | ServerMsg::CopyDone => {}
+            other => return Err(PgError::Error(format!("unexpected data: {:?}", other))),
+        }
+        Ok(None)
+    }
+
+    fn copy_not_supported(&mut self) {
+        self.error
+            .get_or_insert_with(|| PgError::Error("COPY TO STDOUT is not supported".to_string()));
+    }
 }
 
+impl<'conn> Iterator for RowIter<'conn> {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Result<Row>> {
+        let conn = self.conn;
+        while !self.done {
+            match conn.read_message(|msg| self.handle(msg)) {
+                Ok(Some(row)) => return Some(row),
+                Ok(None) => {}
+                Err(err) => {
+                    // The connection is broken, or out of step with the
+                    // server, so there is nothing more to read.
+                    conn.state.set(ConnectionState::Disconnected);
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+        self.error.take().map(Err)
+    }
+}
+
+impl<'conn> Drop for RowIter<'conn> {
+    fn drop(&mut self) {
+        for result in self {
+            if let Err(err) = result {
+                ::log::warn!("Error while discarding query results: {}", err);
+            }
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    use std::env;
-
-    use super::Connection;
+    use super::{Connection, PortalStatus};
+    use crate::{
+        error::{ErrorPosition, PgError},
+        row::Column,
+        types::oid,
+        Config, IsolationLevel, SqlState, TransactionOptions, TransactionStatus,
+    };
+    use gres_protocol::messages::server::FieldFormat;
+    use std::{
+        io,
+        panic::{self, AssertUnwindSafe},
+        time::Duration,
+    };
 
     fn init_log() {
         pretty_env_logger::init();
     }
 
+    /// Connect the way psql would, with settings from the PG* environment
+    /// variables and the password from PGPASSWORD or ~/.pgpass.
+    fn connect() -> Connection {
+        Config::new()
+            .connect()
+            .expect("Could not establish connection")
+    }
+
     #[test]
     fn test_connect() {
-        let user_string = env::var("USER").unwrap();
-        let user = user_string.as_ref();
-        let password = Some(user);
-        let host = "127.0.0.1";
-        let database = Some(user);
-        let conn = Connection::new(user, password, host, database);
-        assert!(conn.is_ok());
+        assert!(Config::new().connect().is_ok());
     }
 
     #[test]
@@ -458,29 +1021,634 @@ mod tests {
         assert!(conn.is_err());
     }
 
+    #[test]
+    fn test_connect_with_wrong_password() {
+        let conn = Config::new().password("not the password").connect();
+        assert!(conn.is_err());
+    }
+
     #[test]
     fn test_query() {
-        let user_string = env::var("USER").unwrap();
-        let user = user_string.as_ref();
-        let pass = Some(user);
-        let host = "127.0.0.1";
-        let conn =
-            Connection::new(user, pass, host, Some(user)).expect("Could not establish connection");
+        let conn = connect();
         let data = conn.simple_query("SELECT VERSION();").unwrap();
         assert_eq!(data.len(), 1);
-        let result = &data[0][0];
+        let result = data[0][0].as_ref().unwrap();
         assert_eq!(&result[..10], "PostgreSQL");
+        assert!(conn.simple_query("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_large_result() {
+        let conn = connect();
+        let data = conn
+            .simple_query("SELECT repeat('x', 100), n FROM generate_series(1, 20000) n;")
+            .unwrap();
+        assert_eq!(data.len(), 20000);
+        assert_eq!(data[19999][1].as_deref(), Some("20000"));
+    }
+
+    #[test]
+    fn test_null_and_binary_values() {
+        let conn = connect();
+        let data = conn
+            .simple_query("SELECT NULL, 1, ''::text, '\\x00ff'::bytea;")
+            .unwrap();
+        assert_eq!(
+            data,
+            vec![vec![
+                None,
+                Some("1".to_string()),
+                Some("".to_string()),
+                Some("\\x00ff".to_string())
+            ]]
+        );
+    }
+
+    /// Accept one connection and complete the startup, then hand the socket
+    /// to `session`.
+    fn serve_tcp<F>(session: F) -> (u16, std::thread::JoinHandle<()>)
+    where
+        F: FnOnce(std::net::TcpStream) + Send + 'static,
+    {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = std::thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut length = [0; 4];
+            socket.read_exact(&mut length).unwrap();
+            let mut startup = vec![0; u32::from_be_bytes(length) as usize - 4];
+            socket.read_exact(&mut startup).unwrap();
+            socket
+                .write_all(b"R\0\0\0\x08\0\0\0\0Z\0\0\0\x05I")
+                .unwrap();
+            session(socket)
+        });
+        (port, handle)
+    }
+
+    fn mock_config(port: u16) -> Config {
+        let mut config = Config::new();
+        config
+            .user("gres")
+            .host("127.0.0.1")
+            .port(port)
+            .ssl_mode(crate::tls::SslMode::Disable);
+        config
+    }
+
+    #[test]
+    fn test_read_timeout() {
+        use std::{io::Read, time::Duration, time::Instant};
+
+        let (port, server) = serve_tcp(|mut socket| {
+            // Never answer the query.
+            let mut rest = vec![];
+            let _ = socket.read_to_end(&mut rest);
+        });
+        let conn = Connection::connect(mock_config(port).read_timeout(Duration::from_millis(100)))
+            .unwrap();
+        let start = Instant::now();
+        match conn.simple_query("SELECT 1;") {
+            Err(PgError::Io(err)) => assert!(matches!(
+                err.kind(),
+                io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+            )),
+            other => panic!("Expected a timeout, got {:?}", other),
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+        drop(conn);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_server_hangs_up() {
+        let (port, server) = serve_tcp(drop);
+        let conn = Connection::connect(&mock_config(port)).unwrap();
+        server.join().unwrap();
+        match conn.simple_query("SELECT 1;") {
+            // Depending on timing, the query itself may be rejected.
+            Err(PgError::Io(err)) => assert!(matches!(
+                err.kind(),
+                io::ErrorKind::UnexpectedEof
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::BrokenPipe
+            )),
+            other => panic!("Expected end of stream, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unexpected_message_disconnects() {
+        use std::io::{Read, Write};
+
+        let (port, server) = serve_tcp(|mut socket| {
+            // Answer "SELECT 1;" with a BackendKeyData message.
+            let mut query = [0; 15];
+            socket.read_exact(&mut query).unwrap();
+            socket
+                .write_all(b"K\0\0\0\x0c\0\0\0\x01\0\0\0\x02")
+                .unwrap();
+            let mut rest = vec![];
+            let _ = socket.read_to_end(&mut rest);
+        });
+        let conn = Connection::connect(&mock_config(port)).unwrap();
+        match conn.simple_query("SELECT 1;") {
+            Err(PgError::Error(msg)) => assert!(msg.starts_with("unexpected data")),
+            other => panic!("Expected unexpected data, got {:?}", other),
+        }
+        match conn.simple_query("SELECT 1;") {
+            Err(PgError::Error(msg)) => assert!(msg.contains("fell out of step")),
+            other => panic!("Expected a closed connection, got {:?}", other),
+        }
+        drop(conn);
+        server.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket() {
+        use crate::tls::SslMode;
+        use std::{
+            io::{Read, Write},
+            os::unix::net::UnixListener,
+            thread,
+        };
+
+        let dir = std::env::temp_dir().join(format!("gres-unix-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let listener = UnixListener::bind(dir.join(".s.PGSQL.5999")).unwrap();
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            // No SSLRequest is sent, so the first message is the startup.
+            let mut length = [0; 4];
+            socket.read_exact(&mut length).unwrap();
+            let mut startup = vec![0; u32::from_be_bytes(length) as usize - 4];
+            socket.read_exact(&mut startup).unwrap();
+            assert_eq!(&startup[..4], b"\0\x03\0\0");
+            socket.write_all(b"R\0\0\0\x08\0\0\0\x03").unwrap();
+            let mut password = [0; 12];
+            socket.read_exact(&mut password).unwrap();
+            socket
+                .write_all(b"R\0\0\0\x08\0\0\0\0Z\0\0\0\x05I")
+                .unwrap();
+            password
+        });
+
+        // Cleartext passwords are allowed, since the socket is local.
+        let conn = Connection::connect(
+            Config::new()
+                .user("gres")
+                .password("secret")
+                .host(dir.to_str().unwrap())
+                .port(5999)
+                .ssl_mode(SslMode::Require),
+        )
+        .unwrap();
+        assert!(!conn.is_encrypted());
+        assert_eq!(conn.host(), dir.to_str().unwrap());
+        assert_eq!(&server.join().unwrap(), b"p\0\0\0\x0bsecret\0");
+        drop(conn);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_connect_unix_socket() {
+        let conn = Config::new()
+            .host("/var/run/postgresql")
+            .connect()
+            .expect("Could not establish connection");
+        let data = conn
+            .simple_query("SELECT inet_server_addr() IS NULL;")
+            .unwrap();
+        assert_eq!(data, vec![vec![Some("t".to_string())]]);
+    }
+
+    #[test]
+    fn test_query_with_params() {
+        let conn = connect();
+        let rows = conn
+            .query(
+                "SELECT $1::text AS name, $2::int2, $3::int4, $4::int8, $5::numeric, \
+                 $6::bool, $7::bytea, $8::text, $9::float4, $10::float8, $11::\"char\";",
+                &[
+                    &"Robert'); DROP TABLE students;--",
+                    &-2i16,
+                    &40000,
+                    &(1i64 << 40),
+                    &7,
+                    &true,
+                    &&b"\0\xff"[..],
+                    &None::<&str>,
+                    &1.5f32,
+                    &0.1f64,
+                    &'c',
+                ],
+            )
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        let row = &rows[0];
+        assert_eq!(row.get::<&str>("name"), "Robert'); DROP TABLE students;--");
+        assert_eq!(row.get::<i16>(1), -2);
+        assert_eq!(row.get::<i32>(2), 40000);
+        assert_eq!(row.get::<i64>(3), 1 << 40);
+        assert_eq!(row.get::<String>(4), "7");
+        assert!(row.get::<bool>(5));
+        assert_eq!(row.get::<&[u8]>(6), b"\0\xff");
+        assert_eq!(row.get::<Option<String>>(7), None);
+        assert_eq!(row.get::<f32>(8), 1.5);
+        assert_eq!(row.get::<f64>(9), 0.1);
+        assert_eq!(row.get::<char>(10), 'c');
+        assert_eq!(conn.query("SELECT 1;", &[]).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_row_columns() {
+        let conn = connect();
+        let rows = conn
+            .query(
+                "SELECT 1::int4 AS id, 'x'::varchar(5) AS code, now() AS at;",
+                &[],
+            )
+            .unwrap();
+        let columns = rows[0].columns();
+        assert_eq!(
+            columns.iter().map(Column::name).collect::<Vec<_>>(),
+            vec!["id", "code", "at"]
+        );
+        assert_eq!(columns[1].type_oid(), oid::VARCHAR);
+        assert_eq!(columns[1].type_modifier(), 9);
+        assert_eq!(columns[0].format(), FieldFormat::Binary);
+        // Types without a binary decoding are fetched as text.
+        assert_eq!(columns[2].format(), FieldFormat::Text);
+        assert!(!rows[0].get::<String>("at").is_empty());
+        assert!(rows[0].try_get::<String>("id").is_err());
+    }
+
+    #[test]
+    fn test_prepared_statement_reuse() {
+        let conn = connect();
+        let stmt = conn
+            .prepare("SELECT n FROM generate_series(1, $1) n;")
+            .unwrap();
+        assert_eq!(stmt.param_types(), &[23]);
+        assert_eq!(stmt.query(&[&3]).unwrap().len(), 3);
+        assert_eq!(stmt.query(&[&"5"]).unwrap().len(), 5);
+        assert!(stmt.query(&[]).is_err());
+        assert!(stmt.query(&[&(1i64 << 40)]).is_err());
+        drop(stmt);
+        assert_eq!(conn.transaction_status(), TransactionStatus::Idle);
+    }
+
+    #[test]
+    fn test_query_errors() {
+        let conn = connect();
+        assert!(conn.prepare("SELEKT 1;").is_err());
+        assert!(conn.query("SELECT 1 / $1::int4;", &[&0]).is_err());
+        // The connection is still usable afterwards.
+        let rows = conn.query("SELECT $1::text;", &[&"ok"]).unwrap();
+        assert_eq!(rows[0].get::<&str>(0), "ok");
+    }
+
+    #[test]
+    fn test_copy_not_supported() {
+        let conn = connect();
+        conn.simple_query("CREATE TEMPORARY TABLE gres_copy (n int4);")
+            .unwrap();
+        let err = conn.simple_query("COPY gres_copy FROM STDIN;").unwrap_err();
+        assert_eq!(err.code(), Some(&SqlState::QueryCanceled));
+        conn.simple_query("INSERT INTO gres_copy VALUES (1), (2);")
+            .unwrap();
+        match conn.simple_query("COPY gres_copy TO STDOUT;") {
+            Err(PgError::Error(msg)) => assert_eq!(msg, "COPY TO STDOUT is not supported"),
+            other => panic!("Expected an error, got {:?}", other),
+        }
+        // The connection is still usable afterwards.
+        assert_eq!(conn.transaction_status(), TransactionStatus::Idle);
+        let rows = conn
+            .simple_query("SELECT count(*) FROM gres_copy;")
+            .unwrap();
+        assert_eq!(rows, vec![vec![Some("2".to_string())]]);
+    }
+
+    #[test]
+    fn test_row_iter_consumed() {
+        let conn = connect();
+        let sum: i64 = conn
+            .query_iter("SELECT n FROM generate_series(1, $1::int4) n;", &[&100_000])
+            .unwrap()
+            .map(|row| row.unwrap().get::<i32>(0) as i64)
+            .sum();
+        assert_eq!(sum, 5_000_050_000);
+        let rows: Vec<_> = conn
+            .simple_query_iter("SELECT 'a' UNION ALL SELECT 'b';")
+            .unwrap()
+            .map(|row| row.unwrap().get::<String>(0))
+            .collect();
+        assert_eq!(rows, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_row_iter_dropped_early() {
+        let conn = connect();
+        {
+            let mut rows = conn
+                .simple_query_iter("SELECT n FROM generate_series(1, 100000) n;")
+                .unwrap();
+            assert_eq!(rows.next().unwrap().unwrap().get::<&str>(0), "1");
+            // A second query cannot start until the rows have been read.
+            assert!(conn.simple_query("SELECT 1;").is_err());
+        }
+        let handle = conn
+            .prepare("SELECT n FROM generate_series(1, $1) n;")
+            .unwrap();
+        assert_eq!(handle.query_iter(&[&1000]).unwrap().take(3).count(), 3);
+        assert_eq!(handle.query(&[&2]).unwrap().len(), 2);
+        assert_eq!(
+            conn.simple_query("SELECT 1;").unwrap(),
+            vec![vec![Some("1".into())]]
+        );
+    }
+
+    #[test]
+    fn test_row_iter_error_mid_stream() {
+        let conn = connect();
+        let mut rows = conn
+            .query_iter("SELECT 10 / (n - 3) FROM generate_series(1, 5) n;", &[])
+            .unwrap();
+        assert_eq!(rows.next().unwrap().unwrap().get::<i32>(0), -5);
+        assert_eq!(rows.next().unwrap().unwrap().get::<i32>(0), -10);
+        assert!(rows.next().unwrap().is_err());
+        assert!(rows.next().is_none());
+        drop(rows);
+        assert_eq!(conn.transaction_status(), TransactionStatus::Idle);
+        let rows = conn.query("SELECT $1::text;", &[&"ok"]).unwrap();
+        assert_eq!(rows[0].get::<&str>(0), "ok");
+    }
+
+    #[test]
+    fn test_portal_fetch() {
+        let conn = connect();
+        let handle = conn
+            .prepare("SELECT n FROM generate_series(1, $1) n;")
+            .unwrap();
+        assert!(handle.bind("outside".to_string(), &[&10]).is_err());
+
+        conn.simple_query("BEGIN;").unwrap();
+        let portal = handle.bind("cursor".to_string(), &[&10]).unwrap();
+        assert_eq!(portal.columns()[0].name(), "n");
+        let fetch = portal.fetch(4).unwrap();
+        assert_eq!(fetch.status, PortalStatus::Suspended);
+        assert_eq!(fetch.rows[0].get::<i32>(0), 1);
+        assert_eq!(fetch.rows.len(), 4);
+        // Other queries can run between fetches.
+        assert_eq!(conn.query("SELECT $1::int4;", &[&5]).unwrap().len(), 1);
+        assert_eq!(portal.fetch(4).unwrap().rows[3].get::<i32>(0), 8);
+        let fetch = portal.fetch(4).unwrap();
+        assert_eq!(fetch.rows.len(), 2);
+        assert!(fetch.is_complete());
+        assert_eq!(fetch.status, PortalStatus::Complete("SELECT 2".to_string()));
+        portal.close().unwrap();
+        assert!(handle.bind("cursor".to_string(), &[&3]).is_ok());
+        conn.simple_query("COMMIT;").unwrap();
+        assert_eq!(conn.transaction_status(), TransactionStatus::Idle);
+    }
+
+    #[test]
+    fn test_portal_errors() {
+        let conn = connect();
+        let handle = conn
+            .prepare("SELECT 10 / (3 - n) FROM generate_series(1, $1::int4) n;")
+            .unwrap();
+        conn.simple_query("BEGIN;").unwrap();
+        let portal = handle.bind("failing".to_string(), &[&5]).unwrap();
+        assert_eq!(portal.fetch(1).unwrap().rows[0].get::<i32>(0), 5);
+        assert!(portal.fetch(0).is_err());
+        assert_eq!(conn.transaction_status(), TransactionStatus::Failed);
+        drop(portal);
+        conn.simple_query("ROLLBACK;").unwrap();
+        assert_eq!(conn.query("SELECT 1;", &[]).unwrap().len(), 1);
+    }
+
+    fn count(conn: &Connection, table: &str) -> i64 {
+        let rows = conn
+            .query(&format!("SELECT count(*) FROM {};", table), &[])
+            .unwrap();
+        rows[0].get(0)
+    }
+
+    #[test]
+    fn test_transaction_commit_and_rollback() {
+        let conn = connect();
+        conn.simple_query("CREATE TEMP TABLE tx_test (n int4);")
+            .unwrap();
+
+        let tx = conn.transaction().unwrap();
+        assert!(conn.transaction().is_err());
+        tx.query("INSERT INTO tx_test VALUES ($1);", &[&1]).unwrap();
+        tx.commit().unwrap();
+        assert_eq!(conn.transaction_status(), TransactionStatus::Idle);
+        assert_eq!(count(&conn, "tx_test"), 1);
+
+        let tx = conn.transaction().unwrap();
+        tx.query("INSERT INTO tx_test VALUES ($1);", &[&2]).unwrap();
+        tx.rollback().unwrap();
+        assert_eq!(count(&conn, "tx_test"), 1);
+
+        {
+            let tx = conn.transaction().unwrap();
+            tx.query("INSERT INTO tx_test VALUES ($1);", &[&3]).unwrap();
+        }
+        assert_eq!(conn.transaction_status(), TransactionStatus::Idle);
+        assert_eq!(count(&conn, "tx_test"), 1);
+
+        let panicked = panic::catch_unwind(AssertUnwindSafe(|| {
+            let tx = conn.transaction().unwrap();
+            tx.query("INSERT INTO tx_test VALUES ($1);", &[&4]).unwrap();
+            panic!("abandon the transaction");
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(conn.transaction_status(), TransactionStatus::Idle);
+        assert_eq!(count(&conn, "tx_test"), 1);
+    }
+
+    #[test]
+    fn test_transaction_commit_after_error() {
+        let conn = connect();
+        let tx = conn.transaction().unwrap();
+        assert!(tx.simple_query("SELECT 1/0;").is_err());
+        assert!(tx.commit().is_err());
+        assert_eq!(conn.transaction_status(), TransactionStatus::Idle);
+    }
+
+    #[test]
+    fn test_nested_transactions() {
+        let conn = connect();
+        conn.simple_query("CREATE TEMP TABLE nested_test (n int4);")
+            .unwrap();
+        let mut tx = conn.transaction().unwrap();
+        tx.query("INSERT INTO nested_test VALUES (1);", &[])
+            .unwrap();
+        {
+            let mut inner = tx.transaction().unwrap();
+            inner
+                .query("INSERT INTO nested_test VALUES (2);", &[])
+                .unwrap();
+            let innermost = inner.transaction().unwrap();
+            assert!(innermost.simple_query("SELECT 1/0;").is_err());
+            // Dropping the failed savepoint makes the outer ones usable again.
+            drop(innermost);
+            inner.commit().unwrap();
+        }
+        {
+            let inner = tx.transaction().unwrap();
+            inner
+                .query("INSERT INTO nested_test VALUES (3);", &[])
+                .unwrap();
+            inner.rollback().unwrap();
+        }
+        assert_eq!(conn.transaction_status(), TransactionStatus::InTransaction);
+        tx.commit().unwrap();
+        assert_eq!(count(&conn, "nested_test"), 2);
+    }
+
+    #[test]
+    fn test_transaction_options() {
+        let conn = connect();
+        let mut options = TransactionOptions::new();
+        options
+            .isolation_level(IsolationLevel::Serializable)
+            .read_only(true)
+            .deferrable(true);
+        let tx = conn.transaction_with(&options).unwrap();
+        assert_eq!(
+            tx.simple_query("SHOW transaction_isolation;").unwrap(),
+            vec![vec![Some("serializable".to_string())]]
+        );
+        assert!(tx
+            .simple_query("CREATE TEMP TABLE ro_test (n int4);")
+            .is_err());
+        tx.rollback().unwrap();
+    }
+
+    /// Run a SERIALIZABLE transaction that increments a counter, while
+    /// `other` updates the same row for the first `conflicts` attempts.
+    fn increment_with_conflicts(
+        conn: &Connection,
+        other: &Connection,
+        table: &str,
+        options: &mut TransactionOptions,
+        conflicts: u32,
+    ) -> (u32, Result<i32, PgError>) {
+        conn.simple_query(&format!("DROP TABLE IF EXISTS {};", table))
+            .unwrap();
+        conn.simple_query(&format!(
+            "CREATE TABLE {} (n int4); INSERT INTO {0} VALUES (0);",
+            table
+        ))
+        .unwrap();
+        options
+            .isolation_level(IsolationLevel::Serializable)
+            .retry_backoff(Duration::from_millis(1));
+        let mut attempts = 0;
+        let result = conn.run_in_transaction(options, |tx| {
+            attempts += 1;
+            let n: i32 = tx.query(&format!("SELECT n FROM {};", table), &[])?[0].get(0);
+            if attempts <= conflicts {
+                other.simple_query(&format!("UPDATE {} SET n = n + 100;", table))?;
+            }
+            tx.query(&format!("UPDATE {} SET n = $1;", table), &[&(n + 1)])?;
+            Ok(n + 1)
+        });
+        conn.simple_query(&format!("DROP TABLE {};", table))
+            .unwrap();
+        (attempts, result)
+    }
+
+    #[test]
+    fn test_run_in_transaction_retries() {
+        let conn = connect();
+        let other = connect();
+        let mut options = TransactionOptions::new();
+        let (attempts, result) =
+            increment_with_conflicts(&conn, &other, "retry_test", &mut options, 2);
+        assert_eq!(attempts, 3);
+        assert_eq!(result.unwrap(), 201);
+        assert_eq!(conn.transaction_status(), TransactionStatus::Idle);
+    }
+
+    #[test]
+    fn test_run_in_transaction_gives_up() {
+        let conn = connect();
+        let other = connect();
+        let mut options = TransactionOptions::new();
+        options.max_attempts(2);
+        let (attempts, result) =
+            increment_with_conflicts(&conn, &other, "retry_give_up_test", &mut options, 5);
+        assert_eq!(attempts, 2);
+        assert_eq!(
+            result.unwrap_err().code(),
+            Some(&SqlState::SerializationFailure)
+        );
+    }
+
+    #[test]
+    fn test_run_in_transaction_other_errors() {
+        let conn = connect();
+        let mut attempts = 0;
+        let result = conn.run_in_transaction(&TransactionOptions::new(), |tx| {
+            attempts += 1;
+            tx.simple_query("SELECT 1/0;")
+        });
+        assert_eq!(attempts, 1);
+        assert_eq!(result.unwrap_err().code(), Some(&SqlState::DivisionByZero));
+        assert_eq!(conn.transaction_status(), TransactionStatus::Idle);
+    }
+
+    #[test]
+    fn test_db_error() {
+        let conn = connect();
+        conn.simple_query("CREATE TEMP TABLE db_error_test (id int4 PRIMARY KEY);")
+            .unwrap();
+        conn.query("INSERT INTO db_error_test VALUES ($1);", &[&1])
+            .unwrap();
+        let err = conn
+            .query("INSERT INTO db_error_test VALUES ($1);", &[&1])
+            .unwrap_err();
+        let db_error = err.as_db_error().expect("a server error");
+        assert_eq!(db_error.code(), &SqlState::UniqueViolation);
+        assert!(db_error.code().is_integrity_constraint_violation());
+        assert_eq!(db_error.severity(), "ERROR");
+        assert_eq!(db_error.table(), Some("db_error_test"));
+        assert_eq!(db_error.constraint(), Some("db_error_test_pkey"));
+        assert_eq!(db_error.detail(), Some("Key (id)=(1) already exists."));
+        assert!(db_error.routine().is_some());
+
+        let err = conn.simple_query("SELECT nope;").unwrap_err();
+        assert_eq!(
+            err.as_db_error().unwrap().position(),
+            Some(&ErrorPosition::Original(8))
+        );
+    }
+
+    #[test]
+    fn test_transaction_status() {
+        let conn = connect();
+        assert_eq!(conn.transaction_status(), TransactionStatus::Idle);
+        conn.simple_query("BEGIN;").unwrap();
+        assert_eq!(conn.transaction_status(), TransactionStatus::InTransaction);
+        assert!(conn.simple_query("SELECT 1/0;").is_err());
+        assert_eq!(conn.transaction_status(), TransactionStatus::Failed);
+        conn.simple_query("ROLLBACK;").unwrap();
+        assert_eq!(conn.transaction_status(), TransactionStatus::Idle);
     }
 
     #[test]
     fn test_crud() {
         init_log();
-        let user_string = env::var("USER").unwrap();
-        let user = user_string.as_ref();
-        let pass = Some(user);
-        let host = "127.0.0.1";
-        let conn =
-            Connection::new(user, pass, host, Some(user)).expect("Could not establish connection");
+        let conn = connect();
 
         conn.simple_query("DROP TABLE IF EXISTS pg_rust_test_crud;")
             .expect("query should not fail");
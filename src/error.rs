@@ -3,7 +3,11 @@ use std::io;
 use std::str::Utf8Error;
 use std::error::Error;
 
-use gres_protocol::ProtocolError;
+use crate::sqlstate::SqlState;
+use gres_protocol::{
+    messages::server::{NoticeBody, Position, Severity},
+    ProtocolError,
+};
 
 #[derive(Debug)]
 pub enum PgError {
@@ -12,7 +16,10 @@ pub enum PgError {
     IntParse(ParseIntError),
     ProtocolError(ProtocolError),
     Error(String),
-    //ServerError(NoticeBody),
+    /// A value could not be read from the named or numbered column.
+    Column(String, ColumnError),
+    /// The server reported an error.
+    Db(Box<DbError>),
     Unauthenticated,
     Other,
 }
@@ -25,7 +32,10 @@ impl fmt::Display for PgError {
             PgError::IntParse(ref err) => err.fmt(f),
             PgError::ProtocolError(ref err) => err.fmt(f),
             PgError::Error(ref string) => write!(f, "Error: {}", string),
-            // PgError::ServerError(err) => write!(f, "ServerError: {:?}", err),
+            PgError::Column(ref column, ref err) => {
+                write!(f, "Error reading column {}: {}", column, err)
+            }
+            PgError::Db(ref err) => err.fmt(f),
             PgError::Unauthenticated => write!(f, "Unauthenticated"),
             PgError::Other => write!(f, "An unknown error occured"),
         }
@@ -40,13 +50,217 @@ impl Error for PgError {
             PgError::IntParse(ref err) => Some(err),
             PgError::ProtocolError(ref err) => Some(err),
             PgError::Error(..) => None,
-            // PgError::ServerError(err) => None,
+            PgError::Column(_, ColumnError::Decode(ref err)) => Some(&**err),
+            PgError::Column(..) => None,
+            PgError::Db(ref err) => Some(&**err),
             PgError::Unauthenticated => None,
             PgError::Other => None,
         }
     }
 }
 
+impl PgError {
+    /// The error reported by the server, if this is one.
+    pub fn as_db_error(&self) -> Option<&DbError> {
+        match *self {
+            PgError::Db(ref err) => Some(&**err),
+            _ => None,
+        }
+    }
+
+    /// The SQLSTATE code of an error reported by the server.
+    pub fn code(&self) -> Option<&SqlState> {
+        self.as_db_error().map(DbError::code)
+    }
+}
+
+/// Where in the query an error occurred, as a 1-based character index.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ErrorPosition {
+    /// A position in the query sent by the client.
+    Original(usize),
+    /// A position in a query generated internally by the server, such as
+    /// the body of a PL/pgSQL function.
+    Internal { position: usize, query: String },
+}
+
+/// An error reported by the server, with all the fields of its
+/// ErrorResponse.  Fields the server did not send are `None`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DbError {
+    severity: String,
+    parsed_severity: Option<Severity>,
+    code: SqlState,
+    message: String,
+    detail: Option<String>,
+    hint: Option<String>,
+    position: Option<ErrorPosition>,
+    where_: Option<String>,
+    schema: Option<String>,
+    table: Option<String>,
+    column: Option<String>,
+    datatype: Option<String>,
+    constraint: Option<String>,
+    file: Option<String>,
+    line: Option<u32>,
+    routine: Option<String>,
+}
+
+impl DbError {
+    /// The severity, possibly translated into the server's language.
+    pub fn severity(&self) -> &str {
+        &self.severity
+    }
+
+    /// The untranslated severity, which servers before 9.6 do not send.
+    pub fn parsed_severity(&self) -> Option<Severity> {
+        self.parsed_severity
+    }
+
+    /// The SQLSTATE code.
+    pub fn code(&self) -> &SqlState {
+        &self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn detail(&self) -> Option<&str> {
+        self.detail.as_deref()
+    }
+
+    pub fn hint(&self) -> Option<&str> {
+        self.hint.as_deref()
+    }
+
+    pub fn position(&self) -> Option<&ErrorPosition> {
+        self.position.as_ref()
+    }
+
+    /// The call stack where the error occurred, such as PL/pgSQL function
+    /// names and line numbers.
+    pub fn where_(&self) -> Option<&str> {
+        self.where_.as_deref()
+    }
+
+    pub fn schema(&self) -> Option<&str> {
+        self.schema.as_deref()
+    }
+
+    pub fn table(&self) -> Option<&str> {
+        self.table.as_deref()
+    }
+
+    pub fn column(&self) -> Option<&str> {
+        self.column.as_deref()
+    }
+
+    pub fn datatype(&self) -> Option<&str> {
+        self.datatype.as_deref()
+    }
+
+    /// The name of the violated constraint.
+    pub fn constraint(&self) -> Option<&str> {
+        self.constraint.as_deref()
+    }
+
+    /// The server source file that reported the error.
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    pub fn line(&self) -> Option<u32> {
+        self.line
+    }
+
+    /// The server source routine that reported the error.
+    pub fn routine(&self) -> Option<&str> {
+        self.routine.as_deref()
+    }
+}
+
+impl<'a> From<&NoticeBody<'a>> for DbError {
+    fn from(body: &NoticeBody<'a>) -> DbError {
+        let field = |indicator| body.field(indicator).map(str::to_string);
+        DbError {
+            severity: body.severity_localized().to_string(),
+            parsed_severity: body.severity(),
+            code: SqlState::from_code(body.code()),
+            message: body.message().to_string(),
+            detail: body.detail().map(str::to_string),
+            hint: body.hint().map(str::to_string),
+            position: body.position().map(|position| match *position {
+                Position::Public(position) => ErrorPosition::Original(position),
+                Position::Internal { position, query } => ErrorPosition::Internal {
+                    position,
+                    query: query.to_string(),
+                },
+            }),
+            where_: field('W'),
+            schema: field('s'),
+            table: field('t'),
+            column: field('c'),
+            datatype: field('d'),
+            constraint: field('n'),
+            file: field('F'),
+            line: body.field('L').and_then(|line| line.parse().ok()),
+            routine: field('R'),
+        }
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} (SQLSTATE {})",
+            self.severity, self.message, self.code
+        )?;
+        if let Some(ref detail) = self.detail {
+            write!(f, "\nDETAIL: {}", detail)?;
+        }
+        if let Some(ref hint) = self.hint {
+            write!(f, "\nHINT: {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for DbError {}
+
+/// Why a column could not be read from a row.
+#[derive(Debug)]
+pub enum ColumnError {
+    /// The row has no such column.
+    NotFound,
+    /// The column is NULL, but the requested type is not an `Option`.
+    UnexpectedNull,
+    /// The requested type cannot be decoded from the column's type.
+    WrongType {
+        type_oid: u32,
+        rust_type: &'static str,
+    },
+    /// The value could not be decoded.
+    Decode(Box<PgError>),
+}
+
+impl fmt::Display for ColumnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ColumnError::NotFound => write!(f, "no such column"),
+            ColumnError::UnexpectedNull => {
+                write!(f, "unexpected NULL; use an Option to accept NULL")
+            }
+            ColumnError::WrongType {
+                type_oid,
+                rust_type,
+            } => write!(f, "cannot convert type {} to {}", type_oid, rust_type),
+            ColumnError::Decode(ref err) => err.fmt(f),
+        }
+    }
+}
+
 impl From<io::Error> for PgError {
     fn from(err: io::Error) -> PgError {
         PgError::Io(err)
@@ -68,10 +282,100 @@ impl From<ProtocolError> for PgError {
     fn from(err: ProtocolError) -> PgError {
         PgError::ProtocolError(err)
     }
-
 }
-/* impl From<NoticeBody<'a>> for PgError {
+
+impl<'a> From<NoticeBody<'a>> for PgError {
     fn from(err: NoticeBody<'a>) -> PgError {
-        PgError::ServerError(err.make_static())
+        PgError::Db(Box::new(DbError::from(&err)))
     }
-}*/
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gres_protocol::messages::server::ServerMsg;
+
+    impl DbError {
+        /// Build an error from the fields of an ErrorResponse.
+        pub(crate) fn from_fields(fields: &[(char, &str)]) -> DbError {
+            let mut body = vec![];
+            for (indicator, value) in fields {
+                body.push(*indicator as u8);
+                body.extend(value.as_bytes());
+                body.push(0);
+            }
+            body.push(0);
+            let mut bytes = vec![b'E'];
+            bytes.extend(&(body.len() as u32 + 4).to_be_bytes());
+            bytes.extend(body);
+            match ServerMsg::from_slice(&bytes).unwrap() {
+                ServerMsg::ErrorResponse(body) => DbError::from(&body),
+                other => panic!("Expected ErrorResponse, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_db_error_fields() {
+        let err = DbError::from_fields(&[
+            ('S', "ERROR"),
+            ('V', "ERROR"),
+            ('C', "23503"),
+            ('M', "insert or update violates foreign key constraint"),
+            ('D', "Key (user_id)=(7) is not present."),
+            ('H', "Insert the user first."),
+            ('p', "5"),
+            ('q', "SELECT 1"),
+            ('W', "SQL function \"f\""),
+            ('s', "public"),
+            ('t', "orders"),
+            ('c', "user_id"),
+            ('d', "int4"),
+            ('n', "orders_user_id_fkey"),
+            ('F', "ri_triggers.c"),
+            ('L', "2596"),
+            ('R', "ri_ReportViolation"),
+        ]);
+        assert_eq!(err.severity(), "ERROR");
+        assert_eq!(err.parsed_severity(), Some(Severity::Error));
+        assert_eq!(err.code(), &SqlState::ForeignKeyViolation);
+        assert_eq!(err.hint(), Some("Insert the user first."));
+        assert_eq!(
+            err.position(),
+            Some(&ErrorPosition::Internal {
+                position: 5,
+                query: "SELECT 1".to_string()
+            })
+        );
+        assert_eq!(err.where_(), Some("SQL function \"f\""));
+        assert_eq!(err.schema(), Some("public"));
+        assert_eq!(err.table(), Some("orders"));
+        assert_eq!(err.column(), Some("user_id"));
+        assert_eq!(err.datatype(), Some("int4"));
+        assert_eq!(err.constraint(), Some("orders_user_id_fkey"));
+        assert_eq!(err.file(), Some("ri_triggers.c"));
+        assert_eq!(err.line(), Some(2596));
+        assert_eq!(err.routine(), Some("ri_ReportViolation"));
+        assert_eq!(
+            err.to_string(),
+            "ERROR: insert or update violates foreign key constraint (SQLSTATE 23503)\n\
+             DETAIL: Key (user_id)=(7) is not present.\n\
+             HINT: Insert the user first."
+        );
+
+        let err = PgError::Db(Box::new(err));
+        assert_eq!(err.code(), Some(&SqlState::ForeignKeyViolation));
+        assert!(err.as_db_error().is_some());
+        assert!(PgError::Other.as_db_error().is_none());
+    }
+
+    #[test]
+    fn test_db_error_minimal() {
+        let err = DbError::from_fields(&[('S', "FATAL"), ('C', "28P01"), ('M', "bad password")]);
+        assert_eq!(err.parsed_severity(), None);
+        assert_eq!(err.detail(), None);
+        assert_eq!(err.position(), None);
+        assert_eq!(err.line(), None);
+        assert_eq!(err.to_string(), "FATAL: bad password (SQLSTATE 28P01)");
+    }
+}
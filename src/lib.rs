@@ -1,9 +1,23 @@
 extern crate crypto;
 use std::result;
-pub use connection::Connection;
+pub use config::Config;
+pub use connection::{Connection, RowIter};
+pub use gres_protocol::messages::server::TransactionStatus;
+pub use row::{FromRow, Row};
+pub use sqlstate::SqlState;
+#[cfg(feature = "derive")]
+pub use gres_derive::FromRow;
+pub use transaction::{IsolationLevel, Transaction, TransactionOptions};
+pub use types::{FromSql, ToSql};
 
 pub mod auth;
+pub mod config;
 pub mod connection;
 pub mod error;
+pub mod row;
+pub mod sqlstate;
+pub mod tls;
+pub mod transaction;
+pub mod types;
 
 pub type Result<T> = result::Result<T, error::PgError>;
@@ -0,0 +1,293 @@
+//! Typed access to the rows returned by a query.
+use crate::{
+    error::{ColumnError, PgError},
+    types::{FromSql, Oid},
+    Result,
+};
+use gres_protocol::messages::server::{DataRow, FieldDescription, FieldFormat};
+use std::{any, fmt, ops::Range, sync::Arc};
+
+/// A column of a query result, as described by the server.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Column {
+    name: String,
+    table_oid: Oid,
+    column_id: i16,
+    type_oid: Oid,
+    type_size: i16,
+    type_modifier: i32,
+    format: FieldFormat,
+}
+
+impl Column {
+    pub(crate) fn new(field: &FieldDescription<'_>) -> Column {
+        Column {
+            name: field.name().to_string(),
+            table_oid: field.table_oid(),
+            column_id: field.column_id(),
+            type_oid: field.type_oid(),
+            type_size: field.type_size(),
+            type_modifier: field.type_modifier(),
+            format: field.format(),
+        }
+    }
+
+    /// Set the format the column's values will be sent in.  A statement
+    /// description does not know the format until the statement is bound.
+    pub(crate) fn with_format(mut self, format: FieldFormat) -> Column {
+        self.format = format;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The OID of the table this column comes from, or zero if it is not a
+    /// table column.
+    pub fn table_oid(&self) -> Oid {
+        self.table_oid
+    }
+
+    /// The attribute number of the column within its table, or zero if it
+    /// is not a table column.
+    pub fn column_id(&self) -> i16 {
+        self.column_id
+    }
+
+    pub fn type_oid(&self) -> Oid {
+        self.type_oid
+    }
+
+    /// The size of the data type, as in `pg_type.typlen`.  Negative values
+    /// denote variable-width types.
+    pub fn type_size(&self) -> i16 {
+        self.type_size
+    }
+
+    /// The type modifier, as in `pg_attribute.atttypmod`.
+    pub fn type_modifier(&self) -> i32 {
+        self.type_modifier
+    }
+
+    pub fn format(&self) -> FieldFormat {
+        self.format
+    }
+}
+
+/// A way of picking a column out of a row: by position, or by name.
+pub trait RowIndex: fmt::Display {
+    /// The position of the column in `columns`, if there is one.
+    fn index(&self, columns: &[Column]) -> Option<usize>;
+}
+
+impl RowIndex for usize {
+    fn index(&self, columns: &[Column]) -> Option<usize> {
+        if *self < columns.len() {
+            Some(*self)
+        } else {
+            None
+        }
+    }
+}
+
+/// Names pick the first column with that name.
+impl RowIndex for str {
+    fn index(&self, columns: &[Column]) -> Option<usize> {
+        columns.iter().position(|column| column.name == self)
+    }
+}
+
+impl<T: RowIndex + ?Sized> RowIndex for &T {
+    fn index(&self, columns: &[Column]) -> Option<usize> {
+        (**self).index(columns)
+    }
+}
+
+/// A row of a query result.
+#[derive(Clone)]
+pub struct Row {
+    columns: Arc<[Column]>,
+    body: Vec<u8>,
+    /// The location of each value in `body`, or `None` for NULL.
+    cells: Vec<Option<Range<usize>>>,
+}
+
+impl Row {
+    pub(crate) fn new(columns: Arc<[Column]>, row: &DataRow<'_>) -> Result<Row> {
+        if row.len() != columns.len() {
+            return Err(PgError::Error(format!(
+                "Data row has {} values, but {} columns were described",
+                row.len(),
+                columns.len()
+            )));
+        }
+        let mut body = Vec::with_capacity(row.cells().iter().flatten().map(|c| c.len()).sum());
+        let cells = row
+            .cells()
+            .iter()
+            .map(|cell| {
+                cell.map(|value| {
+                    let start = body.len();
+                    body.extend_from_slice(value);
+                    start..body.len()
+                })
+            })
+            .collect();
+        Ok(Row {
+            columns,
+            body,
+            cells,
+        })
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// Decode the value of a column, by position or by name.
+    ///
+    /// Panics if the column does not exist or cannot be decoded as `T`;
+    /// use `try_get` to handle those errors.
+    pub fn get<'a, T: FromSql<'a>>(&'a self, idx: impl RowIndex) -> T {
+        match self.try_get(idx) {
+            Ok(value) => value,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Decode the value of a column, by position or by name.
+    pub fn try_get<'a, T: FromSql<'a>>(&'a self, idx: impl RowIndex) -> Result<T> {
+        let column_error = |err| PgError::Column(idx.to_string(), err);
+        let i = idx
+            .index(&self.columns)
+            .ok_or_else(|| column_error(ColumnError::NotFound))?;
+        let column = &self.columns[i];
+        if !T::accepts(column.type_oid) {
+            return Err(column_error(ColumnError::WrongType {
+                type_oid: column.type_oid,
+                rust_type: any::type_name::<T>(),
+            }));
+        }
+        let raw = self.cells[i].clone().map(|range| &self.body[range]);
+        T::from_sql_nullable(column.type_oid, column.format, raw).map_err(|err| match raw {
+            None => column_error(ColumnError::UnexpectedNull),
+            Some(_) => column_error(ColumnError::Decode(Box::new(err))),
+        })
+    }
+}
+
+/// A type that can be built from a row, usually with `#[derive(FromRow)]`.
+///
+/// The derive reads each field from the column of the same name.  Fields
+/// can be customized with `#[gres(...)]` attributes:
+///
+/// * `rename = "column"` reads the field from a differently named column.
+/// * `default` uses `Default::default()` if the column is missing.
+/// * `flatten` builds the field from the same row with its own `FromRow`.
+///   It cannot be combined with the other attributes.
+/// * `try_from = "Type"` reads the column as `Type`, and converts it to the
+///   field type with `TryFrom`.
+pub trait FromRow: Sized {
+    fn from_row(row: &Row) -> Result<Self>;
+}
+
+impl fmt::Debug for Row {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut map = f.debug_map();
+        for (column, cell) in self.columns.iter().zip(&self.cells) {
+            let raw = cell.clone().map(|range| &self.body[range]);
+            map.entry(&column.name, &raw);
+        }
+        map.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::oid;
+
+    fn column(name: &str, type_oid: Oid, format: FieldFormat) -> Column {
+        Column {
+            name: name.to_string(),
+            table_oid: 0,
+            column_id: 0,
+            type_oid,
+            type_size: -1,
+            type_modifier: -1,
+            format,
+        }
+    }
+
+    fn row() -> Row {
+        let columns: Arc<[Column]> = vec![
+            column("id", oid::INT4, FieldFormat::Binary),
+            column("name", oid::TEXT, FieldFormat::Binary),
+            column("note", oid::TEXT, FieldFormat::Text),
+            column("age", oid::INT2, FieldFormat::Text),
+        ]
+        .into();
+        let data = DataRow::new(vec![Some(&[0, 0, 0, 7]), Some(b"ann"), None, Some(b"x")]);
+        Row::new(columns, &data).unwrap()
+    }
+
+    #[test]
+    fn test_get_by_index_and_name() {
+        let row = row();
+        assert_eq!(row.len(), 4);
+        assert_eq!(row.columns()[1].name(), "name");
+        assert_eq!(row.get::<i32>(0), 7);
+        assert_eq!(row.get::<i64>("id"), 7);
+        assert_eq!(row.get::<&str>("name"), "ann");
+        assert_eq!(row.get::<Option<String>>(2), None);
+    }
+
+    #[test]
+    fn test_try_get_errors() {
+        let row = row();
+        match row.try_get::<i32>(4) {
+            Err(PgError::Column(column, ColumnError::NotFound)) => assert_eq!(column, "4"),
+            other => panic!("Expected NotFound, got {:?}", other),
+        }
+        match row.try_get::<i32>("missing") {
+            Err(PgError::Column(column, ColumnError::NotFound)) => assert_eq!(column, "missing"),
+            other => panic!("Expected NotFound, got {:?}", other),
+        }
+        match row.try_get::<String>("note") {
+            Err(PgError::Column(column, ColumnError::UnexpectedNull)) => {
+                assert_eq!(column, "note")
+            }
+            other => panic!("Expected UnexpectedNull, got {:?}", other),
+        }
+        match row.try_get::<bool>("id") {
+            Err(PgError::Column(_, ColumnError::WrongType { type_oid, .. })) => {
+                assert_eq!(type_oid, oid::INT4)
+            }
+            other => panic!("Expected WrongType, got {:?}", other),
+        }
+        let err = row.try_get::<i16>("age").unwrap_err();
+        assert!(matches!(err, PgError::Column(_, ColumnError::Decode(_))));
+        assert!(err.to_string().starts_with("Error reading column age: "));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error reading column 9: no such column")]
+    fn test_get_panics() {
+        row().get::<i32>(9);
+    }
+
+    #[test]
+    fn test_row_length_mismatch() {
+        let columns: Arc<[Column]> = vec![column("id", oid::INT4, FieldFormat::Text)].into();
+        assert!(Row::new(columns, &DataRow::new(vec![])).is_err());
+    }
+}
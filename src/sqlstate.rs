@@ -0,0 +1,69 @@
+//! SQLSTATE codes reported by the server.
+use std::fmt;
+
+include!(concat!(env!("OUT_DIR"), "/sqlstate.rs"));
+
+impl SqlState {
+    /// The class of the code: its first two characters.
+    pub fn class(&self) -> &str {
+        self.code().get(..2).unwrap_or("")
+    }
+}
+
+impl From<&str> for SqlState {
+    fn from(code: &str) -> SqlState {
+        SqlState::from_code(code)
+    }
+}
+
+impl fmt::Display for SqlState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_code() {
+        assert_eq!(SqlState::from_code("23505"), SqlState::UniqueViolation);
+        assert_eq!(SqlState::from_code("40P01"), SqlState::DeadlockDetected);
+        assert_eq!(
+            SqlState::from_code("99999"),
+            SqlState::Other("99999".to_string())
+        );
+        assert_eq!(SqlState::ForeignKeyViolation.code(), "23503");
+        assert_eq!(SqlState::Other("99999".to_string()).code(), "99999");
+        assert_eq!(SqlState::UniqueViolation.to_string(), "23505");
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(
+            SqlState::SerializationFailure.name(),
+            Some("serialization_failure")
+        );
+        // Two codes share the name string_data_right_truncation.
+        assert_eq!(SqlState::WarningStringDataRightTruncation.code(), "01004");
+        assert_eq!(SqlState::StringDataRightTruncation.code(), "22001");
+        assert_eq!(
+            SqlState::WarningStringDataRightTruncation.name(),
+            SqlState::StringDataRightTruncation.name()
+        );
+        assert_eq!(SqlState::from_code("99999").name(), None);
+    }
+
+    #[test]
+    fn test_classes() {
+        assert!(SqlState::UniqueViolation.is_integrity_constraint_violation());
+        assert!(SqlState::CheckViolation.is_integrity_constraint_violation());
+        assert!(!SqlState::SyntaxError.is_integrity_constraint_violation());
+        assert!(SqlState::SyntaxError.is_syntax_error_or_access_rule_violation());
+        assert!(SqlState::SerializationFailure.is_transaction_rollback());
+        assert!(SqlState::FdwError.is_foreign_data_wrapper_error());
+        assert!(SqlState::from_code("23999").is_integrity_constraint_violation());
+        assert_eq!(SqlState::Other(String::new()).class(), "");
+    }
+}
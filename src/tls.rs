@@ -0,0 +1,688 @@
+use crate::{error::PgError, Result};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::{
+    io::{self, Read, Write},
+    net,
+    str::FromStr,
+};
+
+/// How hard to insist on an encrypted connection, like libpq's `sslmode`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SslMode {
+    /// Never use TLS.
+    Disable,
+    /// Use TLS if the server supports it, without verifying its
+    /// certificate.  As in libpq, a failed handshake is retried on a new
+    /// connection without TLS.
+    #[default]
+    Prefer,
+    /// Always use TLS.  The server certificate is only verified if root
+    /// certificates are configured.
+    Require,
+    /// Always use TLS, and verify that the server certificate is signed by
+    /// a trusted root.
+    VerifyCa,
+    /// Like `VerifyCa`, and also verify that the certificate matches the
+    /// host name.
+    VerifyFull,
+}
+
+impl SslMode {
+    fn requires_tls(self) -> bool {
+        !matches!(self, SslMode::Disable | SslMode::Prefer)
+    }
+}
+
+impl FromStr for SslMode {
+    type Err = PgError;
+
+    fn from_str(s: &str) -> Result<SslMode> {
+        match s {
+            "disable" => Ok(SslMode::Disable),
+            "prefer" => Ok(SslMode::Prefer),
+            "require" => Ok(SslMode::Require),
+            "verify-ca" => Ok(SslMode::VerifyCa),
+            "verify-full" => Ok(SslMode::VerifyFull),
+            _ => Err(PgError::Error(format!("Invalid sslmode value: {:?}", s))),
+        }
+    }
+}
+
+/// TLS settings for a connection: the `sslmode`, trusted root certificates
+/// and an optional client certificate.  Certificates and keys are DER
+/// encoded.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TlsConfig {
+    mode: SslMode,
+    root_certs: Vec<Vec<u8>>,
+    client_cert: Option<(Vec<Vec<u8>>, Vec<u8>)>,
+}
+
+impl TlsConfig {
+    pub fn new(mode: SslMode) -> TlsConfig {
+        TlsConfig {
+            mode,
+            ..TlsConfig::default()
+        }
+    }
+
+    pub fn mode(&self) -> SslMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: SslMode) -> &mut TlsConfig {
+        self.mode = mode;
+        self
+    }
+
+    pub fn add_root_cert_der(&mut self, cert: Vec<u8>) -> &mut TlsConfig {
+        self.root_certs.push(cert);
+        self
+    }
+
+    /// Trust every certificate in a PEM file, such as `root.crt`.
+    #[cfg(feature = "tls")]
+    pub fn add_root_certs_pem(&mut self, pem: &[u8]) -> Result<&mut TlsConfig> {
+        let certs = rustls_pemfile::certs(&mut &pem[..])?;
+        if certs.is_empty() {
+            return Err(PgError::Error(
+                "No certificates found in PEM data".to_string(),
+            ));
+        }
+        self.root_certs.extend(certs);
+        Ok(self)
+    }
+
+    pub fn client_cert_der(&mut self, chain: Vec<Vec<u8>>, key: Vec<u8>) -> &mut TlsConfig {
+        self.client_cert = Some((chain, key));
+        self
+    }
+
+    /// Authenticate with a client certificate chain and private key, given
+    /// as PEM.
+    #[cfg(feature = "tls")]
+    pub fn client_cert_pem(&mut self, chain: &[u8], key: &[u8]) -> Result<&mut TlsConfig> {
+        let chain = rustls_pemfile::certs(&mut &chain[..])?;
+        if chain.is_empty() {
+            return Err(PgError::Error(
+                "No certificates found in PEM data".to_string(),
+            ));
+        }
+        let mut key = key;
+        let key = loop {
+            match rustls_pemfile::read_one(&mut key)? {
+                Some(rustls_pemfile::Item::PKCS8Key(key))
+                | Some(rustls_pemfile::Item::RSAKey(key))
+                | Some(rustls_pemfile::Item::ECKey(key)) => break key,
+                Some(_) => continue,
+                None => {
+                    return Err(PgError::Error(
+                        "No private key found in PEM data".to_string(),
+                    ))
+                }
+            }
+        };
+        Ok(self.client_cert_der(chain, key))
+    }
+}
+
+/// The socket underneath a connection.
+#[derive(Debug)]
+pub(crate) enum Stream {
+    Tcp(net::TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<rustls::StreamOwned<rustls::ClientConnection, net::TcpStream>>),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    pub(crate) fn set_read_timeout(&self, timeout: Option<std::time::Duration>) -> io::Result<()> {
+        match self {
+            Stream::Tcp(socket) => socket.set_read_timeout(timeout),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.sock.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Stream::Unix(socket) => socket.set_read_timeout(timeout),
+        }
+    }
+
+    pub(crate) fn set_write_timeout(&self, timeout: Option<std::time::Duration>) -> io::Result<()> {
+        match self {
+            Stream::Tcp(socket) => socket.set_write_timeout(timeout),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.sock.set_write_timeout(timeout),
+            #[cfg(unix)]
+            Stream::Unix(socket) => socket.set_write_timeout(timeout),
+        }
+    }
+
+    pub(crate) fn is_encrypted(&self) -> bool {
+        match self {
+            #[cfg(feature = "tls")]
+            Stream::Tls(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the stream is a Unix domain socket, which never leaves the
+    /// machine.
+    pub(crate) fn is_local(&self) -> bool {
+        match self {
+            #[cfg(unix)]
+            Stream::Unix(_) => true,
+            _ => false,
+        }
+    }
+
+    /// The DER-encoded certificate presented by the server, if the stream is
+    /// encrypted.
+    pub(crate) fn peer_certificate(&self) -> Option<&[u8]> {
+        match self {
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream
+                .conn
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .map(|cert| cert.0.as_slice()),
+            _ => None,
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(socket) => socket.read(buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(socket) => socket.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(socket) => socket.write(buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(socket) => socket.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(socket) => socket.flush(),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.flush(),
+            #[cfg(unix)]
+            Stream::Unix(socket) => socket.flush(),
+        }
+    }
+}
+
+/// Open a socket with `connect` and negotiate encryption on it, according
+/// to the `sslmode`.  This must happen before the startup message is sent.
+pub(crate) fn negotiate<F>(connect: F, host: &str, config: &TlsConfig) -> Result<Stream>
+where
+    F: Fn() -> Result<net::TcpStream>,
+{
+    if config.mode == SslMode::Disable {
+        return Ok(Stream::Tcp(connect()?));
+    }
+    negotiate_tls(connect, host, config)
+}
+
+#[cfg(not(feature = "tls"))]
+fn negotiate_tls<F>(connect: F, _host: &str, config: &TlsConfig) -> Result<Stream>
+where
+    F: Fn() -> Result<net::TcpStream>,
+{
+    if config.mode.requires_tls() {
+        Err(PgError::Error(format!(
+            "sslmode {:?} requires gres to be built with the \"tls\" feature",
+            config.mode
+        )))
+    } else {
+        Ok(Stream::Tcp(connect()?))
+    }
+}
+
+#[cfg(feature = "tls")]
+fn negotiate_tls<F>(connect: F, host: &str, config: &TlsConfig) -> Result<Stream>
+where
+    F: Fn() -> Result<net::TcpStream>,
+{
+    use gres_protocol::messages::client::{Message, SslRequest};
+
+    let mut socket = connect()?;
+    socket.write_all(&SslRequest.to_bytes())?;
+    let mut response = [0];
+    socket.read_exact(&mut response)?;
+    match response[0] {
+        b'S' => {}
+        b'N' if config.mode.requires_tls() => {
+            return Err(PgError::Error(format!(
+                "sslmode {:?} requires TLS, but the server does not support it",
+                config.mode
+            )))
+        }
+        b'N' => return Ok(Stream::Tcp(socket)),
+        other => {
+            return Err(PgError::Error(format!(
+                "Unexpected response to SSLRequest: {:?}",
+                other as char
+            )))
+        }
+    }
+
+    match handshake(socket, host, config) {
+        // The server may have hung up, so start over on a new socket.
+        Err(err) if config.mode == SslMode::Prefer => {
+            ::log::info!("TLS handshake failed, reconnecting without TLS: {}", err);
+            Ok(Stream::Tcp(connect()?))
+        }
+        result => result,
+    }
+}
+
+#[cfg(feature = "tls")]
+fn handshake(mut socket: net::TcpStream, host: &str, config: &TlsConfig) -> Result<Stream> {
+    use std::{convert::TryFrom, sync::Arc};
+
+    let server_name = rustls::ServerName::try_from(host)
+        .map_err(|err| PgError::Error(format!("Invalid TLS server name {:?}: {}", host, err)))?;
+    let mut conn = rustls::ClientConnection::new(Arc::new(client_config(config)?), server_name)
+        .map_err(tls_error)?;
+    while conn.is_handshaking() {
+        conn.complete_io(&mut socket)?;
+    }
+    Ok(Stream::Tls(Box::new(rustls::StreamOwned::new(
+        conn, socket,
+    ))))
+}
+
+#[cfg(feature = "tls")]
+fn tls_error(err: rustls::Error) -> PgError {
+    PgError::Error(format!("TLS error: {}", err))
+}
+
+#[cfg(feature = "tls")]
+fn client_config(config: &TlsConfig) -> Result<rustls::ClientConfig> {
+    use std::sync::Arc;
+
+    let verifies_ca = config.mode == SslMode::VerifyCa || config.mode == SslMode::VerifyFull;
+    if verifies_ca && config.root_certs.is_empty() {
+        return Err(PgError::Error(format!(
+            "sslmode {:?} requires root certificates",
+            config.mode
+        )));
+    }
+
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in &config.root_certs {
+        roots
+            .add(&rustls::Certificate(cert.clone()))
+            .map_err(|err| PgError::Error(format!("Invalid root certificate: {}", err)))?;
+    }
+    let verifier: Arc<dyn rustls::client::ServerCertVerifier> = match config.mode {
+        SslMode::VerifyFull => Arc::new(rustls::client::WebPkiVerifier::new(roots, None)),
+        SslMode::VerifyCa => Arc::new(verifier::CaOnly(rustls::client::WebPkiVerifier::new(
+            roots, None,
+        ))),
+        // As in libpq, `require` verifies the CA only if root certificates
+        // have been provided.
+        _ if !config.root_certs.is_empty() => Arc::new(verifier::CaOnly(
+            rustls::client::WebPkiVerifier::new(roots, None),
+        )),
+        _ => Arc::new(verifier::NoVerification),
+    };
+
+    let builder = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(verifier);
+    match &config.client_cert {
+        Some((chain, key)) => builder
+            .with_client_auth_cert(
+                chain.iter().cloned().map(rustls::Certificate).collect(),
+                rustls::PrivateKey(key.clone()),
+            )
+            .map_err(tls_error),
+        None => Ok(builder.with_no_client_auth()),
+    }
+}
+
+#[cfg(feature = "tls")]
+mod verifier {
+    use rustls::{
+        client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+        Certificate, CertificateError, Error, ServerName,
+    };
+    use std::time::SystemTime;
+
+    /// Verifies the certificate chain, but not the host name.
+    pub struct CaOnly(pub WebPkiVerifier);
+
+    impl ServerCertVerifier for CaOnly {
+        fn verify_server_cert(
+            &self,
+            end_entity: &Certificate,
+            intermediates: &[Certificate],
+            server_name: &ServerName,
+            scts: &mut dyn Iterator<Item = &[u8]>,
+            ocsp_response: &[u8],
+            now: SystemTime,
+        ) -> Result<ServerCertVerified, Error> {
+            // The chain is checked before the name, so a name mismatch
+            // means the chain itself is trusted.
+            match self.0.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                scts,
+                ocsp_response,
+                now,
+            ) {
+                Err(Error::InvalidCertificate(CertificateError::NotValidForName)) => {
+                    Ok(ServerCertVerified::assertion())
+                }
+                result => result,
+            }
+        }
+    }
+
+    /// Accepts any certificate, for `sslmode=prefer` and `require`.
+    pub struct NoVerification;
+
+    impl ServerCertVerifier for NoVerification {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &Certificate,
+            _intermediates: &[Certificate],
+            _server_name: &ServerName,
+            _scts: &mut dyn Iterator<Item = &[u8]>,
+            _ocsp_response: &[u8],
+            _now: SystemTime,
+        ) -> Result<ServerCertVerified, Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tls"))]
+mod tests {
+    use super::*;
+    use crate::{Config, Connection};
+    use std::{net::TcpListener, sync::Arc, thread};
+
+    struct Pki {
+        ca: rcgen::Certificate,
+        ca_der: Vec<u8>,
+    }
+
+    impl Pki {
+        fn new(name: &str) -> Pki {
+            let mut params = rcgen::CertificateParams::new(vec![]);
+            params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+            params
+                .distinguished_name
+                .push(rcgen::DnType::CommonName, name);
+            let ca = rcgen::Certificate::from_params(params).unwrap();
+            let ca_der = ca.serialize_der().unwrap();
+            Pki { ca, ca_der }
+        }
+
+        fn issue(&self, names: &[&str]) -> rcgen::Certificate {
+            let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+            rcgen::Certificate::from_params(rcgen::CertificateParams::new(names)).unwrap()
+        }
+
+        fn server_config(&self, client_ca: Option<&Pki>) -> rustls::ServerConfig {
+            let cert = self.issue(&["localhost"]);
+            let chain = vec![rustls::Certificate(
+                cert.serialize_der_with_signer(&self.ca).unwrap(),
+            )];
+            let key = rustls::PrivateKey(cert.serialize_private_key_der());
+            let builder = rustls::ServerConfig::builder().with_safe_defaults();
+            let builder = match client_ca {
+                Some(client_ca) => {
+                    let mut roots = rustls::RootCertStore::empty();
+                    roots
+                        .add(&rustls::Certificate(client_ca.ca_der.clone()))
+                        .unwrap();
+                    builder.with_client_cert_verifier(
+                        rustls::server::AllowAnyAuthenticatedClient::new(roots).boxed(),
+                    )
+                }
+                None => builder.with_no_client_auth(),
+            };
+            builder.with_single_cert(chain, key).unwrap()
+        }
+    }
+
+    /// Reply to the startup message as if authentication succeeded, then
+    /// wait for the client to hang up.
+    fn serve_session<S: Read + Write>(stream: &mut S) -> io::Result<()> {
+        let mut length = [0; 4];
+        stream.read_exact(&mut length)?;
+        let mut startup = vec![0; u32::from_be_bytes(length) as usize - 4];
+        stream.read_exact(&mut startup)?;
+        stream.write_all(b"R\0\0\0\x08\0\0\0\0Z\0\0\0\x05I")?;
+        stream.flush()?;
+        let mut rest = vec![];
+        let _ = stream.read_to_end(&mut rest);
+        Ok(())
+    }
+
+    /// Accept a single connection, answer its SSLRequest with `response`,
+    /// and complete a TLS handshake if that was 'S'.  The thread returns
+    /// true if a session was served.
+    fn serve(
+        response: u8,
+        config: Option<rustls::ServerConfig>,
+    ) -> (u16, thread::JoinHandle<bool>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut request = [0; 8];
+            socket.read_exact(&mut request).unwrap();
+            assert_eq!(request, [0, 0, 0, 8, 0x04, 0xd2, 0x16, 0x2f]);
+            socket.write_all(&[response]).unwrap();
+            match config {
+                Some(config) => {
+                    let conn = rustls::ServerConnection::new(Arc::new(config)).unwrap();
+                    let mut stream = rustls::StreamOwned::new(conn, socket);
+                    serve_session(&mut stream).is_ok()
+                }
+                None => serve_session(&mut socket).is_ok(),
+            }
+        });
+        (port, handle)
+    }
+
+    /// Connect without consulting the environment, which could add
+    /// certificates.
+    fn connect(host: &str, port: u16, tls: &TlsConfig) -> Result<Connection> {
+        Connection::connect(
+            Config::new()
+                .user("gres")
+                .dbname("gres")
+                .host(host)
+                .port(port)
+                .tls(tls.clone()),
+        )
+    }
+
+    #[test]
+    fn test_sslmode_from_str() {
+        assert_eq!(
+            "verify-full".parse::<SslMode>().unwrap(),
+            SslMode::VerifyFull
+        );
+        assert_eq!("disable".parse::<SslMode>().unwrap(), SslMode::Disable);
+        assert!("allow-ish".parse::<SslMode>().is_err());
+    }
+
+    #[test]
+    fn test_verify_full() {
+        let pki = Pki::new("gres test CA");
+        let (port, server) = serve(b'S', Some(pki.server_config(None)));
+        let mut tls = TlsConfig::new(SslMode::VerifyFull);
+        tls.add_root_cert_der(pki.ca_der.clone());
+        let conn = connect("localhost", port, &tls).unwrap();
+        assert!(conn.is_encrypted());
+        drop(conn);
+        assert!(server.join().unwrap());
+    }
+
+    #[test]
+    fn test_verify_full_rejects_wrong_host() {
+        let pki = Pki::new("gres test CA");
+        let (port, server) = serve(b'S', Some(pki.server_config(None)));
+        let mut tls = TlsConfig::new(SslMode::VerifyFull);
+        tls.add_root_cert_der(pki.ca_der.clone());
+        assert!(connect("127.0.0.1", port, &tls).is_err());
+        assert!(!server.join().unwrap());
+    }
+
+    #[test]
+    fn test_verify_ca_ignores_host() {
+        let pki = Pki::new("gres test CA");
+        let (port, server) = serve(b'S', Some(pki.server_config(None)));
+        let mut tls = TlsConfig::new(SslMode::VerifyCa);
+        tls.add_root_cert_der(pki.ca_der.clone());
+        let conn = connect("127.0.0.1", port, &tls).unwrap();
+        assert!(conn.is_encrypted());
+        drop(conn);
+        assert!(server.join().unwrap());
+    }
+
+    #[test]
+    fn test_verify_ca_rejects_unknown_ca() {
+        let pki = Pki::new("gres test CA");
+        let other = Pki::new("some other CA");
+        let (port, server) = serve(b'S', Some(pki.server_config(None)));
+        let mut tls = TlsConfig::new(SslMode::VerifyCa);
+        tls.add_root_cert_der(other.ca_der.clone());
+        assert!(connect("localhost", port, &tls).is_err());
+        assert!(!server.join().unwrap());
+    }
+
+    #[test]
+    fn test_verify_requires_root_certs() {
+        let pki = Pki::new("gres test CA");
+        let (port, server) = serve(b'S', Some(pki.server_config(None)));
+        assert!(connect("localhost", port, &TlsConfig::new(SslMode::VerifyFull)).is_err());
+        assert!(!server.join().unwrap());
+    }
+
+    #[test]
+    fn test_require_without_verification() {
+        let pki = Pki::new("gres test CA");
+        let (port, server) = serve(b'S', Some(pki.server_config(None)));
+        let conn = connect("127.0.0.1", port, &TlsConfig::new(SslMode::Require)).unwrap();
+        assert!(conn.is_encrypted());
+        drop(conn);
+        assert!(server.join().unwrap());
+    }
+
+    #[test]
+    fn test_server_without_tls() {
+        let (port, server) = serve(b'N', None);
+        assert!(connect("127.0.0.1", port, &TlsConfig::new(SslMode::Require)).is_err());
+        server.join().unwrap();
+
+        let (port, server) = serve(b'N', None);
+        let conn = connect("127.0.0.1", port, &TlsConfig::new(SslMode::Prefer)).unwrap();
+        assert!(!conn.is_encrypted());
+        drop(conn);
+        assert!(server.join().unwrap());
+    }
+
+    #[test]
+    fn test_prefer_falls_back_after_failed_handshake() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            // Agree to TLS, then hang up instead of completing the handshake.
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut request = [0; 8];
+            socket.read_exact(&mut request).unwrap();
+            socket.write_all(b"S").unwrap();
+            drop(socket);
+            let (mut socket, _) = listener.accept().unwrap();
+            serve_session(&mut socket).is_ok()
+        });
+        let conn = connect("127.0.0.1", port, &TlsConfig::new(SslMode::Prefer)).unwrap();
+        assert!(!conn.is_encrypted());
+        drop(conn);
+        assert!(server.join().unwrap());
+    }
+
+    #[test]
+    fn test_ssl_request_timeout() {
+        use std::time::{Duration, Instant};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            // Never answer the SSLRequest.
+            let mut rest = vec![];
+            let _ = socket.read_to_end(&mut rest);
+        });
+        let start = Instant::now();
+        let result = Connection::connect(
+            Config::new()
+                .user("gres")
+                .dbname("gres")
+                .host("127.0.0.1")
+                .port(port)
+                .tls(TlsConfig::new(SslMode::Prefer))
+                .read_timeout(Duration::from_millis(100)),
+        );
+        match result {
+            Err(PgError::Io(err)) => assert!(matches!(
+                err.kind(),
+                io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+            )),
+            other => panic!("Expected a timeout, got {:?}", other.map(|_| ())),
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_client_certificate() {
+        let pki = Pki::new("gres test CA");
+        let client_pki = Pki::new("gres client CA");
+        let client_cert = client_pki.issue(&["gres"]);
+        let chain_pem = client_cert
+            .serialize_pem_with_signer(&client_pki.ca)
+            .unwrap();
+        let key_pem = client_cert.serialize_private_key_pem();
+
+        let (port, server) = serve(b'S', Some(pki.server_config(Some(&client_pki))));
+        let mut tls = TlsConfig::new(SslMode::VerifyFull);
+        tls.add_root_certs_pem(pki.ca.serialize_pem().unwrap().as_bytes())
+            .unwrap()
+            .client_cert_pem(chain_pem.as_bytes(), key_pem.as_bytes())
+            .unwrap();
+        let conn = connect("localhost", port, &tls).unwrap();
+        assert!(conn.is_encrypted());
+        drop(conn);
+        assert!(server.join().unwrap());
+
+        // Without the client certificate, the server refuses the handshake.
+        let (port, server) = serve(b'S', Some(pki.server_config(Some(&client_pki))));
+        let mut tls = TlsConfig::new(SslMode::VerifyFull);
+        tls.add_root_cert_der(pki.ca_der.clone());
+        assert!(connect("localhost", port, &tls).is_err());
+        assert!(!server.join().unwrap());
+    }
+}
@@ -0,0 +1,343 @@
+//! Transactions that roll back unless they are committed.
+use crate::{error::PgError, Connection, Result, SqlState, TransactionStatus};
+use rand::Rng;
+use std::{ops::Deref, thread, time::Duration};
+
+/// The isolation level of a transaction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IsolationLevel {
+    ReadUncommitted,
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+impl IsolationLevel {
+    fn sql(self) -> &'static str {
+        match self {
+            IsolationLevel::ReadUncommitted => "READ UNCOMMITTED",
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+            IsolationLevel::RepeatableRead => "REPEATABLE READ",
+            IsolationLevel::Serializable => "SERIALIZABLE",
+        }
+    }
+}
+
+/// Options for starting a transaction.  Anything left unset uses the
+/// server's defaults.
+///
+/// The retry settings are used by `Connection::run_in_transaction`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TransactionOptions {
+    isolation_level: Option<IsolationLevel>,
+    read_only: Option<bool>,
+    deferrable: Option<bool>,
+    max_attempts: u32,
+    retry_backoff: Duration,
+    max_retry_backoff: Duration,
+}
+
+impl Default for TransactionOptions {
+    fn default() -> TransactionOptions {
+        TransactionOptions {
+            isolation_level: None,
+            read_only: None,
+            deferrable: None,
+            max_attempts: 5,
+            retry_backoff: Duration::from_millis(10),
+            max_retry_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl TransactionOptions {
+    pub fn new() -> TransactionOptions {
+        TransactionOptions::default()
+    }
+
+    pub fn isolation_level(&mut self, level: IsolationLevel) -> &mut TransactionOptions {
+        self.isolation_level = Some(level);
+        self
+    }
+
+    pub fn get_isolation_level(&self) -> Option<IsolationLevel> {
+        self.isolation_level
+    }
+
+    pub fn read_only(&mut self, read_only: bool) -> &mut TransactionOptions {
+        self.read_only = Some(read_only);
+        self
+    }
+
+    pub fn get_read_only(&self) -> Option<bool> {
+        self.read_only
+    }
+
+    /// Only has an effect on SERIALIZABLE READ ONLY transactions, which then
+    /// wait for a snapshot that cannot fail with a serialization error.
+    pub fn deferrable(&mut self, deferrable: bool) -> &mut TransactionOptions {
+        self.deferrable = Some(deferrable);
+        self
+    }
+
+    pub fn get_deferrable(&self) -> Option<bool> {
+        self.deferrable
+    }
+
+    /// How many times to run a transaction before giving up on
+    /// serialization failures.  Defaults to 5.
+    pub fn max_attempts(&mut self, max_attempts: u32) -> &mut TransactionOptions {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    pub fn get_max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// How long to wait before the first retry.  The wait doubles after
+    /// each failed attempt, and is randomized so that conflicting clients
+    /// don't retry in lockstep.  Defaults to 10ms.
+    pub fn retry_backoff(&mut self, backoff: Duration) -> &mut TransactionOptions {
+        self.retry_backoff = backoff;
+        self
+    }
+
+    pub fn get_retry_backoff(&self) -> Duration {
+        self.retry_backoff
+    }
+
+    /// The longest wait between attempts.  Defaults to one second.
+    pub fn max_retry_backoff(&mut self, backoff: Duration) -> &mut TransactionOptions {
+        self.max_retry_backoff = backoff;
+        self
+    }
+
+    pub fn get_max_retry_backoff(&self) -> Duration {
+        self.max_retry_backoff
+    }
+
+    /// The longest wait after the `attempt`th failed attempt.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt - 1).unwrap_or(u32::MAX);
+        self.retry_backoff
+            .checked_mul(factor)
+            .map_or(self.max_retry_backoff, |backoff| {
+                backoff.min(self.max_retry_backoff)
+            })
+    }
+
+    fn begin_sql(&self) -> String {
+        let mut modes = vec![];
+        if let Some(level) = self.isolation_level {
+            modes.push(format!("ISOLATION LEVEL {}", level.sql()));
+        }
+        match self.read_only {
+            Some(true) => modes.push("READ ONLY".to_string()),
+            Some(false) => modes.push("READ WRITE".to_string()),
+            None => {}
+        }
+        match self.deferrable {
+            Some(true) => modes.push("DEFERRABLE".to_string()),
+            Some(false) => modes.push("NOT DEFERRABLE".to_string()),
+            None => {}
+        }
+        if modes.is_empty() {
+            "BEGIN;".to_string()
+        } else {
+            format!("BEGIN {};", modes.join(", "))
+        }
+    }
+}
+
+/// An open transaction, or a savepoint within one.
+///
+/// The transaction is rolled back when the guard is dropped, unless it has
+/// been committed.  Queries run through the guard, which derefs to the
+/// `Connection`.
+pub struct Transaction<'conn> {
+    conn: &'conn Connection,
+    /// The savepoint this transaction is nested in, or `None` for the
+    /// top-level transaction.
+    savepoint: Option<String>,
+    depth: u32,
+    done: bool,
+}
+
+impl<'conn> Transaction<'conn> {
+    pub(crate) fn begin(
+        conn: &'conn Connection,
+        options: &TransactionOptions,
+    ) -> Result<Transaction<'conn>> {
+        if conn.transaction_status() != TransactionStatus::Idle {
+            return Err(PgError::Error(
+                "A transaction is already in progress".to_string(),
+            ));
+        }
+        conn.simple_query(&options.begin_sql())?;
+        Ok(Transaction {
+            conn,
+            savepoint: None,
+            depth: 0,
+            done: false,
+        })
+    }
+
+    /// Start a nested transaction, as a savepoint within this one.
+    pub fn transaction(&mut self) -> Result<Transaction<'_>> {
+        let depth = self.depth + 1;
+        let savepoint = format!("gres_savepoint_{}", depth);
+        self.conn
+            .simple_query(&format!("SAVEPOINT {};", savepoint))?;
+        Ok(Transaction {
+            conn: self.conn,
+            savepoint: Some(savepoint),
+            depth,
+            done: false,
+        })
+    }
+
+    /// Commit the transaction, or release its savepoint.
+    ///
+    /// Fails, after rolling back, if an earlier error aborted the
+    /// transaction.
+    pub fn commit(mut self) -> Result<()> {
+        self.done = true;
+        if self.conn.transaction_status() == TransactionStatus::Failed {
+            self.finish_rollback()?;
+            return Err(PgError::Error(
+                "Transaction was rolled back because of an earlier error".to_string(),
+            ));
+        }
+        match &self.savepoint {
+            Some(savepoint) => self
+                .conn
+                .simple_query(&format!("RELEASE SAVEPOINT {};", savepoint)),
+            None => self.conn.simple_query("COMMIT;"),
+        }
+        .map(|_| ())
+    }
+
+    /// Roll back the transaction, or roll back to its savepoint.
+    pub fn rollback(mut self) -> Result<()> {
+        self.done = true;
+        self.finish_rollback()
+    }
+
+    fn finish_rollback(&self) -> Result<()> {
+        match &self.savepoint {
+            Some(savepoint) => self.conn.simple_query(&format!(
+                "ROLLBACK TO SAVEPOINT {0}; RELEASE SAVEPOINT {0};",
+                savepoint
+            )),
+            None => self.conn.simple_query("ROLLBACK;"),
+        }
+        .map(|_| ())
+    }
+}
+
+impl<'conn> Deref for Transaction<'conn> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn
+    }
+}
+
+impl<'conn> Drop for Transaction<'conn> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        if let Err(err) = self.finish_rollback() {
+            ::log::warn!("Error rolling back transaction: {}", err);
+        }
+    }
+}
+
+/// Whether `err` means the transaction conflicted with a concurrent one,
+/// so running it again may succeed: a serialization failure (40001) or a
+/// deadlock (40P01).
+pub fn is_retryable(err: &PgError) -> bool {
+    matches!(
+        err.code(),
+        Some(SqlState::SerializationFailure) | Some(SqlState::DeadlockDetected)
+    )
+}
+
+pub(crate) fn run<T, F>(conn: &Connection, options: &TransactionOptions, mut f: F) -> Result<T>
+where
+    F: FnMut(&mut Transaction<'_>) -> Result<T>,
+{
+    let mut attempt = 1;
+    loop {
+        let result = Transaction::begin(conn, options).and_then(|mut tx| {
+            let value = f(&mut tx)?;
+            tx.commit()?;
+            Ok(value)
+        });
+        match result {
+            Err(ref err) if is_retryable(err) && attempt < options.max_attempts => {
+                let backoff = options.backoff(attempt);
+                let delay = rand::thread_rng().gen_range(backoff / 2..=backoff);
+                ::log::info!(
+                    "Retrying transaction in {:?} after attempt {}: {}",
+                    delay,
+                    attempt,
+                    err
+                );
+                thread::sleep(delay);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::DbError;
+
+    #[test]
+    fn test_begin_sql() {
+        assert_eq!(TransactionOptions::new().begin_sql(), "BEGIN;");
+        let mut options = TransactionOptions::new();
+        options
+            .isolation_level(IsolationLevel::Serializable)
+            .read_only(true)
+            .deferrable(true);
+        assert_eq!(
+            options.begin_sql(),
+            "BEGIN ISOLATION LEVEL SERIALIZABLE, READ ONLY, DEFERRABLE;"
+        );
+        let mut options = TransactionOptions::new();
+        options.read_only(false).deferrable(false);
+        assert_eq!(options.begin_sql(), "BEGIN READ WRITE, NOT DEFERRABLE;");
+    }
+
+    #[test]
+    fn test_backoff() {
+        let mut options = TransactionOptions::new();
+        options
+            .retry_backoff(Duration::from_millis(10))
+            .max_retry_backoff(Duration::from_millis(50));
+        assert_eq!(options.backoff(1), Duration::from_millis(10));
+        assert_eq!(options.backoff(2), Duration::from_millis(20));
+        assert_eq!(options.backoff(3), Duration::from_millis(40));
+        assert_eq!(options.backoff(4), Duration::from_millis(50));
+        assert_eq!(options.backoff(100), Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        let error = |code| {
+            let fields = [('S', "ERROR"), ('C', code), ('M', "conflict")];
+            PgError::Db(Box::new(DbError::from_fields(&fields)))
+        };
+        assert!(is_retryable(&error("40001")));
+        assert!(is_retryable(&error("40P01")));
+        assert!(!is_retryable(&error("23505")));
+        assert!(!is_retryable(&PgError::Other));
+    }
+}
@@ -0,0 +1,579 @@
+//! Conversions between Rust values and Postgres values.
+//!
+//! Query parameters are encoded with `ToSql`, and columns are decoded with
+//! `FromSql`.  Both are keyed by the OID of the Postgres type on the other
+//! side, and support the text and binary wire formats.
+use crate::{error::PgError, Result};
+use gres_protocol::messages::{client::Format, server::FieldFormat};
+use std::{convert::TryFrom, str};
+
+/// The OID identifying a Postgres type.
+pub type Oid = u32;
+
+/// OIDs of the built-in types with conversions in this module.
+pub mod oid {
+    use super::Oid;
+
+    pub const BOOL: Oid = 16;
+    pub const BYTEA: Oid = 17;
+    pub const CHAR: Oid = 18;
+    pub const NAME: Oid = 19;
+    pub const INT8: Oid = 20;
+    pub const INT2: Oid = 21;
+    pub const INT4: Oid = 23;
+    pub const TEXT: Oid = 25;
+    pub const OID: Oid = 26;
+    pub const FLOAT4: Oid = 700;
+    pub const FLOAT8: Oid = 701;
+    pub const UNKNOWN: Oid = 705;
+    pub const BPCHAR: Oid = 1042;
+    pub const VARCHAR: Oid = 1043;
+}
+
+/// Whether columns of type `ty` are fetched in binary format by
+/// `Connection::query`.  Other types are fetched as text.
+pub(crate) fn has_binary_format(ty: Oid) -> bool {
+    matches!(
+        ty,
+        oid::BOOL
+            | oid::BYTEA
+            | oid::CHAR
+            | oid::NAME
+            | oid::INT8
+            | oid::INT2
+            | oid::INT4
+            | oid::TEXT
+            | oid::OID
+            | oid::FLOAT4
+            | oid::FLOAT8
+            | oid::BPCHAR
+            | oid::VARCHAR
+    )
+}
+
+fn is_text_type(ty: Oid) -> bool {
+    matches!(
+        ty,
+        oid::TEXT | oid::VARCHAR | oid::BPCHAR | oid::NAME | oid::UNKNOWN
+    )
+}
+
+/// A value that can be sent as a query parameter.
+pub trait ToSql {
+    /// Encode the value for a parameter of type `ty`, returning the format
+    /// used and the encoded value, or `None` for NULL.
+    ///
+    /// Values are sent in binary when `ty` has a binary encoding for them,
+    /// and as text otherwise, for the server to parse as `ty`.
+    fn to_sql(&self, ty: Oid) -> Result<(Format, Option<Vec<u8>>)>;
+}
+
+fn text(value: impl ToString) -> Result<(Format, Option<Vec<u8>>)> {
+    Ok((Format::Text, Some(value.to_string().into_bytes())))
+}
+
+fn binary(value: impl Into<Vec<u8>>) -> Result<(Format, Option<Vec<u8>>)> {
+    Ok((Format::Binary, Some(value.into())))
+}
+
+impl<T: ToSql + ?Sized> ToSql for &T {
+    fn to_sql(&self, ty: Oid) -> Result<(Format, Option<Vec<u8>>)> {
+        (**self).to_sql(ty)
+    }
+}
+
+impl<T: ToSql> ToSql for Option<T> {
+    fn to_sql(&self, ty: Oid) -> Result<(Format, Option<Vec<u8>>)> {
+        match self {
+            Some(value) => value.to_sql(ty),
+            None => Ok((Format::Text, None)),
+        }
+    }
+}
+
+impl ToSql for bool {
+    fn to_sql(&self, ty: Oid) -> Result<(Format, Option<Vec<u8>>)> {
+        match ty {
+            oid::BOOL => binary(vec![*self as u8]),
+            _ => text(self),
+        }
+    }
+}
+
+fn int_to_sql(value: i64, ty: Oid) -> Result<(Format, Option<Vec<u8>>)> {
+    let out_of_range = || {
+        PgError::Error(format!(
+            "Integer parameter {} is out of range for type {}",
+            value, ty
+        ))
+    };
+    match ty {
+        oid::INT8 => binary(value.to_be_bytes()),
+        oid::INT4 => binary(
+            i32::try_from(value)
+                .map_err(|_| out_of_range())?
+                .to_be_bytes(),
+        ),
+        oid::INT2 => binary(
+            i16::try_from(value)
+                .map_err(|_| out_of_range())?
+                .to_be_bytes(),
+        ),
+        oid::OID => binary(
+            u32::try_from(value)
+                .map_err(|_| out_of_range())?
+                .to_be_bytes(),
+        ),
+        _ => text(value),
+    }
+}
+
+impl ToSql for i16 {
+    fn to_sql(&self, ty: Oid) -> Result<(Format, Option<Vec<u8>>)> {
+        int_to_sql((*self).into(), ty)
+    }
+}
+
+impl ToSql for i32 {
+    fn to_sql(&self, ty: Oid) -> Result<(Format, Option<Vec<u8>>)> {
+        int_to_sql((*self).into(), ty)
+    }
+}
+
+impl ToSql for i64 {
+    fn to_sql(&self, ty: Oid) -> Result<(Format, Option<Vec<u8>>)> {
+        int_to_sql(*self, ty)
+    }
+}
+
+impl ToSql for f32 {
+    fn to_sql(&self, ty: Oid) -> Result<(Format, Option<Vec<u8>>)> {
+        match ty {
+            oid::FLOAT4 => binary(self.to_be_bytes()),
+            oid::FLOAT8 => binary(f64::from(*self).to_be_bytes()),
+            _ => text(self),
+        }
+    }
+}
+
+impl ToSql for f64 {
+    fn to_sql(&self, ty: Oid) -> Result<(Format, Option<Vec<u8>>)> {
+        match ty {
+            oid::FLOAT8 => binary(self.to_be_bytes()),
+            // Let the server round to single precision.
+            _ => text(self),
+        }
+    }
+}
+
+impl ToSql for str {
+    fn to_sql(&self, _ty: Oid) -> Result<(Format, Option<Vec<u8>>)> {
+        text(self)
+    }
+}
+
+impl ToSql for String {
+    fn to_sql(&self, ty: Oid) -> Result<(Format, Option<Vec<u8>>)> {
+        self.as_str().to_sql(ty)
+    }
+}
+
+impl ToSql for char {
+    fn to_sql(&self, ty: Oid) -> Result<(Format, Option<Vec<u8>>)> {
+        match ty {
+            oid::CHAR => match u8::try_from(*self) {
+                Ok(byte) if byte.is_ascii() => binary(vec![byte]),
+                _ => Err(PgError::Error(format!(
+                    "Character {:?} does not fit in type \"char\"",
+                    self
+                ))),
+            },
+            _ => text(self),
+        }
+    }
+}
+
+/// Byte strings are always sent in binary, as `bytea`.
+impl ToSql for [u8] {
+    fn to_sql(&self, _ty: Oid) -> Result<(Format, Option<Vec<u8>>)> {
+        binary(self)
+    }
+}
+
+impl ToSql for Vec<u8> {
+    fn to_sql(&self, ty: Oid) -> Result<(Format, Option<Vec<u8>>)> {
+        self.as_slice().to_sql(ty)
+    }
+}
+
+/// A value that can be decoded from a column.
+pub trait FromSql<'a>: Sized {
+    /// Whether columns of type `ty` can be decoded into this type.
+    fn accepts(ty: Oid) -> bool;
+
+    /// Decode a non-NULL value of type `ty`.
+    fn from_sql(ty: Oid, format: FieldFormat, raw: &'a [u8]) -> Result<Self>;
+
+    /// Decode a value that may be NULL.  By default NULL is an error; only
+    /// `Option` accepts it.
+    fn from_sql_nullable(ty: Oid, format: FieldFormat, raw: Option<&'a [u8]>) -> Result<Self> {
+        match raw {
+            Some(raw) => Self::from_sql(ty, format, raw),
+            None => Err(PgError::Error(
+                "Unexpected NULL value; use an Option to accept NULL".to_string(),
+            )),
+        }
+    }
+}
+
+fn from_text(raw: &[u8]) -> Result<&str> {
+    Ok(str::from_utf8(raw)?)
+}
+
+fn invalid_value(ty: Oid, raw: &[u8]) -> PgError {
+    PgError::Error(format!("Invalid value for type {}: {:?}", ty, raw))
+}
+
+impl<'a, T: FromSql<'a>> FromSql<'a> for Option<T> {
+    fn accepts(ty: Oid) -> bool {
+        T::accepts(ty)
+    }
+
+    fn from_sql(ty: Oid, format: FieldFormat, raw: &'a [u8]) -> Result<Self> {
+        T::from_sql(ty, format, raw).map(Some)
+    }
+
+    fn from_sql_nullable(ty: Oid, format: FieldFormat, raw: Option<&'a [u8]>) -> Result<Self> {
+        match raw {
+            Some(raw) => Self::from_sql(ty, format, raw),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<'a> FromSql<'a> for bool {
+    fn accepts(ty: Oid) -> bool {
+        ty == oid::BOOL
+    }
+
+    fn from_sql(ty: Oid, format: FieldFormat, raw: &'a [u8]) -> Result<Self> {
+        match (format, raw) {
+            (FieldFormat::Binary, [byte]) => Ok(*byte != 0),
+            (FieldFormat::Text, b"t") => Ok(true),
+            (FieldFormat::Text, b"f") => Ok(false),
+            _ => Err(invalid_value(ty, raw)),
+        }
+    }
+}
+
+fn int_from_sql(ty: Oid, format: FieldFormat, raw: &[u8]) -> Result<i64> {
+    match format {
+        FieldFormat::Text => Ok(from_text(raw)?.parse()?),
+        FieldFormat::Binary => match (ty, raw.len()) {
+            (oid::INT2, 2) => Ok(i16::from_be_bytes([raw[0], raw[1]]).into()),
+            (oid::INT4, 4) => Ok(i32::from_be_bytes([raw[0], raw[1], raw[2], raw[3]]).into()),
+            (oid::OID, 4) => Ok(u32::from_be_bytes([raw[0], raw[1], raw[2], raw[3]]).into()),
+            (oid::INT8, 8) => {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(raw);
+                Ok(i64::from_be_bytes(bytes))
+            }
+            _ => Err(invalid_value(ty, raw)),
+        },
+    }
+}
+
+macro_rules! int_from_sql {
+    ($t:ty, $($oid:path),+) => {
+        impl<'a> FromSql<'a> for $t {
+            fn accepts(ty: Oid) -> bool {
+                matches!(ty, $($oid)|+)
+            }
+
+            fn from_sql(ty: Oid, format: FieldFormat, raw: &'a [u8]) -> Result<Self> {
+                let value = int_from_sql(ty, format, raw)?;
+                <$t>::try_from(value).map_err(|_| {
+                    PgError::Error(format!(
+                        "Value {} is out of range for {}",
+                        value,
+                        stringify!($t)
+                    ))
+                })
+            }
+        }
+    };
+}
+
+int_from_sql!(i16, oid::INT2);
+int_from_sql!(i32, oid::INT2, oid::INT4);
+int_from_sql!(i64, oid::INT2, oid::INT4, oid::INT8, oid::OID);
+
+fn float_from_sql(ty: Oid, format: FieldFormat, raw: &[u8]) -> Result<f64> {
+    match format {
+        FieldFormat::Text => from_text(raw)?
+            .parse()
+            .map_err(|_| PgError::Error(format!("Invalid float value: {:?}", raw))),
+        FieldFormat::Binary => match (ty, raw.len()) {
+            (oid::FLOAT4, 4) => Ok(f32::from_be_bytes([raw[0], raw[1], raw[2], raw[3]]).into()),
+            (oid::FLOAT8, 8) => {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(raw);
+                Ok(f64::from_be_bytes(bytes))
+            }
+            _ => Err(invalid_value(ty, raw)),
+        },
+    }
+}
+
+impl<'a> FromSql<'a> for f32 {
+    fn accepts(ty: Oid) -> bool {
+        ty == oid::FLOAT4
+    }
+
+    fn from_sql(ty: Oid, format: FieldFormat, raw: &'a [u8]) -> Result<Self> {
+        // A float4 always fits, since that is all we accept.
+        float_from_sql(ty, format, raw).map(|value| value as f32)
+    }
+}
+
+impl<'a> FromSql<'a> for f64 {
+    fn accepts(ty: Oid) -> bool {
+        matches!(ty, oid::FLOAT4 | oid::FLOAT8)
+    }
+
+    fn from_sql(ty: Oid, format: FieldFormat, raw: &'a [u8]) -> Result<Self> {
+        float_from_sql(ty, format, raw)
+    }
+}
+
+/// Any column can be read as a string in text format, but only text types
+/// in binary format.
+impl<'a> FromSql<'a> for &'a str {
+    fn accepts(_ty: Oid) -> bool {
+        true
+    }
+
+    fn from_sql(ty: Oid, format: FieldFormat, raw: &'a [u8]) -> Result<Self> {
+        match format {
+            FieldFormat::Binary if !is_text_type(ty) => Err(PgError::Error(format!(
+                "Cannot decode binary value of type {} as a string",
+                ty
+            ))),
+            _ => from_text(raw),
+        }
+    }
+}
+
+impl<'a> FromSql<'a> for String {
+    fn accepts(ty: Oid) -> bool {
+        <&str>::accepts(ty)
+    }
+
+    fn from_sql(ty: Oid, format: FieldFormat, raw: &'a [u8]) -> Result<Self> {
+        <&str>::from_sql(ty, format, raw).map(str::to_string)
+    }
+}
+
+impl<'a> FromSql<'a> for char {
+    fn accepts(ty: Oid) -> bool {
+        ty == oid::CHAR || is_text_type(ty)
+    }
+
+    fn from_sql(ty: Oid, format: FieldFormat, raw: &'a [u8]) -> Result<Self> {
+        let s = match (ty, format) {
+            (oid::CHAR, _) => from_text(raw)?,
+            _ => <&str>::from_sql(ty, format, raw)?,
+        };
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(c),
+            _ => Err(PgError::Error(format!(
+                "Expected a single character, found {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Binary `bytea` values are borrowed as is.  Text values are escaped, so
+/// use `Vec<u8>` for them.
+impl<'a> FromSql<'a> for &'a [u8] {
+    fn accepts(ty: Oid) -> bool {
+        ty == oid::BYTEA
+    }
+
+    fn from_sql(_ty: Oid, format: FieldFormat, raw: &'a [u8]) -> Result<Self> {
+        match format {
+            FieldFormat::Binary => Ok(raw),
+            FieldFormat::Text => Err(PgError::Error(
+                "Cannot borrow a text bytea value; decode it as Vec<u8>".to_string(),
+            )),
+        }
+    }
+}
+
+impl<'a> FromSql<'a> for Vec<u8> {
+    fn accepts(ty: Oid) -> bool {
+        ty == oid::BYTEA
+    }
+
+    fn from_sql(_ty: Oid, format: FieldFormat, raw: &'a [u8]) -> Result<Self> {
+        match format {
+            FieldFormat::Binary => Ok(raw.to_vec()),
+            FieldFormat::Text => decode_bytea_hex(raw),
+        }
+    }
+}
+
+/// Decode `bytea` in the `\x` hex output format.
+fn decode_bytea_hex(raw: &[u8]) -> Result<Vec<u8>> {
+    let invalid = || PgError::Error(format!("Invalid hex bytea value: {:?}", raw));
+    let hex = raw.strip_prefix(b"\\x").ok_or_else(invalid)?;
+    if hex.len() % 2 != 0 {
+        return Err(invalid());
+    }
+    hex.chunks(2)
+        .map(|pair| {
+            str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(invalid)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode<T: ToSql>(value: T, ty: Oid) -> (Format, Option<Vec<u8>>) {
+        value.to_sql(ty).unwrap()
+    }
+
+    #[test]
+    fn test_to_sql_binary() {
+        assert_eq!(encode(true, oid::BOOL), (Format::Binary, Some(vec![1])));
+        assert_eq!(
+            encode(-2i16, oid::INT2),
+            (Format::Binary, Some(vec![0xff, 0xfe]))
+        );
+        assert_eq!(
+            encode(1i16, oid::INT8),
+            (Format::Binary, Some(vec![0, 0, 0, 0, 0, 0, 0, 1]))
+        );
+        assert_eq!(
+            encode(1.5f32, oid::FLOAT8),
+            (Format::Binary, Some(1.5f64.to_be_bytes().to_vec()))
+        );
+        assert_eq!(
+            encode('x', oid::CHAR),
+            (Format::Binary, Some(b"x".to_vec()))
+        );
+        assert_eq!(
+            encode(&b"\0\xff"[..], oid::BYTEA),
+            (Format::Binary, Some(vec![0, 0xff]))
+        );
+    }
+
+    #[test]
+    fn test_to_sql_text() {
+        assert_eq!(
+            encode(false, oid::TEXT),
+            (Format::Text, Some(b"false".to_vec()))
+        );
+        assert_eq!(encode(7i64, 1700), (Format::Text, Some(b"7".to_vec())));
+        assert_eq!(
+            encode(0.25f64, oid::FLOAT4),
+            (Format::Text, Some(b"0.25".to_vec()))
+        );
+        assert_eq!(
+            encode("hi", oid::INT4),
+            (Format::Text, Some(b"hi".to_vec()))
+        );
+        assert_eq!(
+            encode('é', oid::TEXT),
+            (Format::Text, Some("é".as_bytes().to_vec()))
+        );
+        assert_eq!(encode(None::<i32>, oid::INT4), (Format::Text, None));
+        assert_eq!(
+            encode(Some(String::from("a")), oid::TEXT),
+            (Format::Text, Some(b"a".to_vec()))
+        );
+    }
+
+    #[test]
+    fn test_to_sql_out_of_range() {
+        assert!(40_000i32.to_sql(oid::INT2).is_err());
+        assert!((-1i64).to_sql(oid::OID).is_err());
+        assert!('é'.to_sql(oid::CHAR).is_err());
+    }
+
+    #[test]
+    fn test_from_sql_binary() {
+        let binary = FieldFormat::Binary;
+        assert!(bool::from_sql(oid::BOOL, binary, &[1]).unwrap());
+        assert_eq!(i32::from_sql(oid::INT2, binary, &[0xff, 0xfe]).unwrap(), -2);
+        assert_eq!(
+            i64::from_sql(oid::INT4, binary, &[0, 1, 0, 0]).unwrap(),
+            65536
+        );
+        assert_eq!(
+            f64::from_sql(oid::FLOAT4, binary, &1.5f32.to_be_bytes()).unwrap(),
+            1.5
+        );
+        assert_eq!(
+            String::from_sql(oid::VARCHAR, binary, b"abc").unwrap(),
+            "abc"
+        );
+        assert_eq!(
+            <&[u8]>::from_sql(oid::BYTEA, binary, b"\0\xff").unwrap(),
+            b"\0\xff"
+        );
+        assert_eq!(char::from_sql(oid::CHAR, binary, b"r").unwrap(), 'r');
+        assert!(String::from_sql(oid::INT4, binary, &[0, 0, 0, 1]).is_err());
+        assert!(i32::from_sql(oid::INT4, binary, &[0, 1]).is_err());
+    }
+
+    #[test]
+    fn test_from_sql_text() {
+        let text = FieldFormat::Text;
+        assert!(!bool::from_sql(oid::BOOL, text, b"f").unwrap());
+        assert_eq!(i16::from_sql(oid::INT2, text, b"-300").unwrap(), -300);
+        assert_eq!(
+            f64::from_sql(oid::FLOAT8, text, b"-Infinity").unwrap(),
+            f64::NEG_INFINITY
+        );
+        assert_eq!(String::from_sql(1700, text, b"1.50").unwrap(), "1.50");
+        assert_eq!(
+            Vec::<u8>::from_sql(oid::BYTEA, text, b"\\x00ff").unwrap(),
+            vec![0, 0xff]
+        );
+        assert!(Vec::<u8>::from_sql(oid::BYTEA, text, b"\\x0").is_err());
+        assert!(<&[u8]>::from_sql(oid::BYTEA, text, b"\\x00").is_err());
+        assert!(char::from_sql(oid::TEXT, text, b"ab").is_err());
+    }
+
+    #[test]
+    fn test_from_sql_nullable() {
+        let text = FieldFormat::Text;
+        assert_eq!(
+            Option::<i32>::from_sql_nullable(oid::INT4, text, None).unwrap(),
+            None
+        );
+        assert_eq!(
+            Option::<i32>::from_sql_nullable(oid::INT4, text, Some(b"5")).unwrap(),
+            Some(5)
+        );
+        assert!(i32::from_sql_nullable(oid::INT4, text, None).is_err());
+    }
+
+    #[test]
+    fn test_accepts() {
+        assert!(i64::accepts(oid::INT2));
+        assert!(!i16::accepts(oid::INT4));
+        assert!(!f32::accepts(oid::FLOAT8));
+        assert!(Option::<bool>::accepts(oid::BOOL));
+        assert!(String::accepts(oid::INT4));
+        assert!(!Vec::<u8>::accepts(oid::TEXT));
+    }
+}